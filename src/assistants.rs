@@ -0,0 +1,265 @@
+/*!
+Assistants and threads
+
+An assistant holds a model and instructions, a thread holds the messages of a conversation.
+Running a thread lets the assistant reply to it asynchronously:
+
+```ignore
+let assistant = client.create_assistant(&model, &AssistantParams::default()).await?;
+let thread = client.create_thread().await?;
+client.add_message(&thread.id, &Completion::user("hello")).await?;
+let run = client.run_thread(&thread.id, &assistant.id).await?;
+let run = client.wait_for_run(&thread.id, &run.id, Duration::from_secs(1)).await?;
+if run.status == RunStatus::Completed {
+    let reply = &client.list_messages(&thread.id).await?[0];
+    println!("{}", reply.text());
+}
+```
+*/
+
+use std::time::Duration;
+
+use reqwest::{Method, RequestBuilder};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    chat::{Completion, CompletionRole},
+    client::Client,
+    model::Model,
+    tools::Tool,
+    Error,
+};
+
+impl Client {
+    /// Creates an assistant answering with the provided model
+    pub async fn create_assistant(
+        &self,
+        model: &Model,
+        params: &AssistantParams,
+    ) -> crate::Result<Assistant> {
+        let mut json = json!(params);
+        json["model"] = json!(model.name);
+        self.send_assistants(
+            self.assistants_request(Method::POST, "/assistants")
+                .json(&json),
+        )
+        .await
+    }
+
+    /// Creates an empty thread
+    pub async fn create_thread(&self) -> crate::Result<Thread> {
+        let req = self
+            .assistants_request(Method::POST, "/threads")
+            .json(&json!({}));
+        self.send_assistants(req).await
+    }
+
+    /**
+    Adds a message to a thread
+
+    Fails if the message is not a user or assistant message or the request failed
+    */
+    pub async fn add_message(
+        &self,
+        thread_id: &str,
+        msg: &Completion,
+    ) -> crate::Result<ThreadMessage> {
+        if !matches!(
+            msg.get_role(),
+            CompletionRole::USER | CompletionRole::ASSISTANT
+        ) {
+            return Err(Error::InvalidInput(
+                "threads only take user and assistant messages".to_string(),
+            ));
+        }
+        let json = json!({ "role": msg.get_role(), "content": msg.content });
+        let path = format!("/threads/{thread_id}/messages");
+        self.send_assistants(self.assistants_request(Method::POST, &path).json(&json))
+            .await
+    }
+
+    /// Lists the messages of a thread, newest first
+    pub async fn list_messages(&self, thread_id: &str) -> crate::Result<Vec<ThreadMessage>> {
+        let path = format!("/threads/{thread_id}/messages");
+        let res: ListResponse<ThreadMessage> = self
+            .send_assistants(self.assistants_request(Method::GET, &path))
+            .await?;
+        Ok(res.data)
+    }
+
+    /// Starts a run letting the assistant reply to the thread, poll it with `get_run` or `wait_for_run`
+    pub async fn run_thread(&self, thread_id: &str, assistant_id: &str) -> crate::Result<Run> {
+        let path = format!("/threads/{thread_id}/runs");
+        let req = self
+            .assistants_request(Method::POST, &path)
+            .json(&json!({ "assistant_id": assistant_id }));
+        self.send_assistants(req).await
+    }
+
+    /// Retrieves the current state of a run
+    pub async fn get_run(&self, thread_id: &str, run_id: &str) -> crate::Result<Run> {
+        let path = format!("/threads/{thread_id}/runs/{run_id}");
+        self.send_assistants(self.assistants_request(Method::GET, &path))
+            .await
+    }
+
+    /**
+    Polls a run every `poll_interval` until it finished or requires action, see `RunStatus::is_settled`
+
+    A run that failed is returned as well, check its status and `last_error`.
+    There is no overall time limit, wrap the call in `tokio::time::timeout` to bound the wait
+    */
+    pub async fn wait_for_run(
+        &self,
+        thread_id: &str,
+        run_id: &str,
+        poll_interval: Duration,
+    ) -> crate::Result<Run> {
+        loop {
+            let run = self.get_run(thread_id, run_id).await?;
+            if run.status.is_settled() {
+                return Ok(run);
+            }
+            tokio::time::sleep(poll_interval).await;
+        }
+    }
+
+    /// The assistants API is versioned through a header
+    fn assistants_request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.request(method, path)
+            .header("OpenAI-Beta", "assistants=v2")
+    }
+
+    async fn send_assistants<T: DeserializeOwned>(&self, req: RequestBuilder) -> crate::Result<T> {
+        let res = self.execute(req).await?;
+        let path = res.url().path().to_string();
+        let res = self.read_text(&path, res).await?;
+        Ok(serde_json::from_str(&res)?)
+    }
+}
+
+/// Optional settings of a new assistant
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct AssistantParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// System instructions the assistant follows in every run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Tool>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Assistant {
+    pub id: String,
+    pub model: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub instructions: Option<String>,
+    /// Seconds since the unix epoch the assistant was created at
+    #[serde(default)]
+    pub created_at: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Thread {
+    pub id: String,
+    /// Seconds since the unix epoch the thread was created at
+    #[serde(default)]
+    pub created_at: Option<u64>,
+}
+
+/// Message of a thread, `content` holds its text and attachments
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ThreadMessage {
+    pub id: String,
+    pub role: CompletionRole,
+    #[serde(default)]
+    pub content: Vec<MessageContent>,
+    /// Run that created the message, None for messages added with `add_message`
+    #[serde(default)]
+    pub run_id: Option<String>,
+}
+
+impl ThreadMessage {
+    /// Returns the text parts joined together, other parts are skipped
+    pub fn text(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|part| match part {
+                MessageContent::Text { text } => Some(text.value.as_str()),
+                MessageContent::Other => None,
+            })
+            .collect()
+    }
+}
+
+/// Part of a thread message, only text is parsed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MessageContent {
+    Text {
+        text: MessageText,
+    },
+    /// Images, files and other parts
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MessageText {
+    pub value: String,
+    /// Citations of uploaded files, left unparsed
+    #[serde(default)]
+    pub annotations: Vec<Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Run {
+    pub id: String,
+    pub thread_id: String,
+    pub assistant_id: String,
+    pub status: RunStatus,
+    /// Why the run failed, None unless the status is `Failed`
+    #[serde(default)]
+    pub last_error: Option<RunError>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RunError {
+    pub code: String,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Queued,
+    InProgress,
+    /// The assistant called tools and waits for their outputs
+    RequiresAction,
+    Cancelling,
+    Cancelled,
+    Failed,
+    Completed,
+    Incomplete,
+    Expired,
+    /// Statuses added to the API after this crate was written
+    #[serde(other)]
+    Unknown,
+}
+
+impl RunStatus {
+    /// Returns if the run won't progress on its own anymore, either because it ended or because it requires action
+    pub fn is_settled(&self) -> bool {
+        !matches!(self, Self::Queued | Self::InProgress | Self::Cancelling)
+    }
+}
+
+#[derive(Deserialize)]
+struct ListResponse<T> {
+    data: Vec<T>,
+}
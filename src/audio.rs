@@ -0,0 +1,523 @@
+use std::{io::Write, time::Duration};
+
+use reqwest::{
+    header::{ACCEPT, CONTENT_TYPE},
+    multipart::{Form, Part},
+    Method,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    client::Client,
+    generation::{Generation, GenerationId, GenerationStatus},
+    model::Model,
+    Error,
+};
+
+const AUDIO_GENERATION_PATH: &str = "/v2/generate/audio";
+const AUDIO_EXTEND_PATH: &str = "/v2/generate/audio/extend";
+
+/// Returns the generated speech as raw audio bytes encoded in the provided format
+pub async fn text_to_speech(
+    api_key: &str,
+    model: &Model,
+    text: &str,
+    voice: &str,
+    format: AudioFormat,
+) -> crate::Result<Vec<u8>> {
+    Client::new(api_key)
+        .text_to_speech(model, text, voice, format)
+        .await
+}
+
+/**
+Uploads audio and returns the recognized text
+
+Fails if the audio is empty or the request failed
+*/
+pub async fn transcribe(
+    api_key: &str,
+    model: &Model,
+    audio: Vec<u8>,
+    filename: &str,
+    params: &TranscriptionParams,
+) -> crate::Result<Transcription> {
+    Client::new(api_key)
+        .transcribe(model, audio, filename, params)
+        .await
+}
+
+/**
+Generates music or sound from the prompt, see `AudioGeneration` for the two kinds of results
+
+Will return an error if request fails
+*/
+pub async fn generate_audio(
+    api_key: &str,
+    model: &Model,
+    prompt: &str,
+    params: &AudioGenerationParams,
+) -> crate::Result<AudioGeneration> {
+    Client::new(api_key)
+        .generate_audio(model, prompt, params)
+        .await
+}
+
+/**
+Continues an audio clip by `extend_by` seconds following the prompt, returns the job's id
+
+Fails if the source is not a completed generation or a supported audio file, or the request failed
+*/
+pub async fn extend_audio(
+    api_key: &str,
+    model: &Model,
+    source: AudioSource,
+    prompt: &str,
+    extend_by: u32,
+) -> crate::Result<GenerationId> {
+    Client::new(api_key)
+        .extend_audio(model, source, prompt, extend_by)
+        .await
+}
+
+/// Retrieves the current state of an audio generation, the url is set once it completed
+pub async fn fetch_generation(api_key: &str, id: &GenerationId) -> crate::Result<Generation> {
+    Client::new(api_key).fetch_audio(id).await
+}
+
+impl Client {
+    /**
+    Generates music or sound from the prompt, see `AudioGeneration` for the two kinds of results
+
+    Will return an error if request fails
+    */
+    pub async fn generate_audio(
+        &self,
+        model: &Model,
+        prompt: &str,
+        params: &AudioGenerationParams,
+    ) -> crate::Result<AudioGeneration> {
+        let mut json = json!(params);
+        json["model"] = json!(model.name);
+        json["prompt"] = json!(prompt);
+        let req = self
+            .request(Method::POST, AUDIO_GENERATION_PATH)
+            .json(&json);
+        let res = self.execute(req).await?;
+
+        let is_json = res
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/json"));
+        if !is_json {
+            return Ok(AudioGeneration::Audio(self.read_bytes(res).await?));
+        }
+        let res = self.read_text(AUDIO_GENERATION_PATH, res).await?;
+        AudioGeneration::from_value(serde_json::from_str(&res)?)
+    }
+
+    /**
+    Continues an audio clip by `extend_by` seconds following the prompt, returns the job's id
+
+    Poll the job with `fetch_audio` or `wait_for_audio` like a regular generation
+
+    Fails if the source is not a completed generation or a supported audio file, or the request failed
+    */
+    pub async fn extend_audio(
+        &self,
+        model: &Model,
+        source: AudioSource,
+        prompt: &str,
+        extend_by: u32,
+    ) -> crate::Result<GenerationId> {
+        match source {
+            AudioSource::Generation(id) => {
+                let generation = self.fetch_audio(&id).await?;
+                if generation.status != GenerationStatus::Completed {
+                    return Err(Error::InvalidInput(format!(
+                        "generation {id} has not completed"
+                    )));
+                }
+                let json = json!({
+                "model": model.name,
+                "prompt": prompt,
+                "generation_id": id,
+                "extend_by": extend_by,
+                });
+                self.start_generation(AUDIO_EXTEND_PATH, &json).await
+            }
+            AudioSource::File { bytes, filename } => {
+                let Some(mime) = sniff_audio(&bytes) else {
+                    return Err(Error::InvalidInput(
+                        "audio is not mp3, wav, ogg, flac or m4a".to_string(),
+                    ));
+                };
+                let file = Part::bytes(bytes).file_name(filename).mime_str(mime)?;
+                let form = Form::new()
+                    .text("model", model.name.clone())
+                    .text("prompt", prompt.to_string())
+                    .text("extend_by", extend_by.to_string())
+                    .part("audio", file);
+                let req = self
+                    .request(Method::POST, AUDIO_EXTEND_PATH)
+                    .multipart(form);
+                let res = self.execute(req).await?;
+                let res = self.read_text(AUDIO_EXTEND_PATH, res).await?;
+                Ok(Generation::from_value(serde_json::from_str(&res)?)?.id)
+            }
+        }
+    }
+
+    /// Retrieves the current state of an audio generation, the url is set once it completed
+    pub async fn fetch_audio(&self, id: &GenerationId) -> crate::Result<Generation> {
+        self.fetch_generation_at(AUDIO_GENERATION_PATH, id).await
+    }
+
+    /**
+    Polls an audio generation every `poll_interval` until it completed or failed
+
+    A failed generation is returned as well, check its status and error.
+    Fails with `Error::Timeout` if the generation is still unfinished after `max_wait`
+    */
+    pub async fn wait_for_audio(
+        &self,
+        id: &GenerationId,
+        poll_interval: Duration,
+        max_wait: Duration,
+    ) -> crate::Result<Generation> {
+        self.wait_for_generation_at(AUDIO_GENERATION_PATH, id, poll_interval, max_wait)
+            .await
+    }
+
+    /// Returns the generated speech as raw audio bytes encoded in the provided format
+    pub async fn text_to_speech(
+        &self,
+        model: &Model,
+        text: &str,
+        voice: &str,
+        format: AudioFormat,
+    ) -> crate::Result<Vec<u8>> {
+        let json = json!({
+        "model": model.name,
+        "text": text,
+        "voice": voice,
+        "response_format": format,
+        });
+        let req = self
+            .request(Method::POST, "/tts")
+            .header(ACCEPT, format.mime())
+            .json(&json);
+        let res = self.execute(req).await?;
+        self.read_bytes(res).await
+    }
+
+    /**
+    Same as `text_to_speech` but writes the audio to `writer` as it arrives instead of returning it,
+    e.g. to a file or an audio device
+
+    Returns the number of bytes written, fails if the request failed or writing failed
+    */
+    pub async fn text_to_speech_to_writer(
+        &self,
+        model: &Model,
+        text: &str,
+        voice: &str,
+        format: AudioFormat,
+        writer: &mut impl Write,
+    ) -> crate::Result<u64> {
+        let json = json!({
+        "model": model.name,
+        "text": text,
+        "voice": voice,
+        "response_format": format,
+        });
+        let req = self
+            .request(Method::POST, "/tts")
+            .header(ACCEPT, format.mime())
+            .json(&json);
+        let res = self.execute_stream(req).await?;
+        self.write_body(res, writer).await
+    }
+
+    /**
+    Uploads audio and returns the recognized text
+
+    Segment timestamps are only returned with `TranscriptionFormat::VerboseJson`
+
+    Fails if the audio is empty or the request failed
+    */
+    pub async fn transcribe(
+        &self,
+        model: &Model,
+        audio: Vec<u8>,
+        filename: &str,
+        params: &TranscriptionParams,
+    ) -> crate::Result<Transcription> {
+        if audio.is_empty() {
+            return Err(Error::InvalidInput("audio file is empty".to_string()));
+        }
+
+        let file = Part::bytes(audio)
+            .file_name(filename.to_string())
+            .mime_str(mime_from_filename(filename))?;
+        let mut form = Form::new()
+            .text("model", model.name.clone())
+            .text("response_format", params.response_format.as_str())
+            .part("file", file);
+        if let Some(language) = &params.language {
+            form = form.text("language", language.clone());
+        }
+
+        let req = self.request(Method::POST, "/stt").multipart(form);
+        let res = self.execute(req).await?;
+        let res = self.read_text("/stt", res).await?;
+        match params.response_format {
+            TranscriptionFormat::Text => Ok(Transcription {
+                text: res,
+                segments: None,
+            }),
+            TranscriptionFormat::VerboseJson => Ok(serde_json::from_str(&res)?),
+        }
+    }
+}
+
+/// Detects the mime type of supported audio files from their first bytes
+fn sniff_audio(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [b'I', b'D', b'3', ..] => Some("audio/mpeg"),
+        // mp3 frame sync
+        [0xff, second, ..] if second & 0xe0 == 0xe0 => Some("audio/mpeg"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some("audio/wav"),
+        [b'O', b'g', b'g', b'S', ..] => Some("audio/ogg"),
+        [b'f', b'L', b'a', b'C', ..] => Some("audio/flac"),
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => Some("audio/mp4"),
+        _ => None,
+    }
+}
+
+/// Infers the mime type of an audio file from its extension, defaults to `application/octet-stream`
+pub fn mime_from_filename(filename: &str) -> &'static str {
+    let extension = filename.rsplit_once('.').map(|(_, ext)| ext.to_lowercase());
+    match extension.as_deref() {
+        Some("mp3") | Some("mpga") | Some("mpeg") => "audio/mpeg",
+        Some("wav") => "audio/wav",
+        Some("ogg") | Some("opus") => "audio/ogg",
+        Some("flac") => "audio/flac",
+        Some("m4a") => "audio/mp4",
+        Some("webm") => "audio/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct TranscriptionParams {
+    /// ISO-639-1 code of the spoken language, improves accuracy and latency
+    pub language: Option<String>,
+    pub response_format: TranscriptionFormat,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TranscriptionFormat {
+    #[default]
+    Text,
+    VerboseJson,
+}
+
+impl TranscriptionFormat {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Text => "text",
+            Self::VerboseJson => "verbose_json",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Transcription {
+    pub text: String,
+    #[serde(default)]
+    pub segments: Option<Vec<TranscriptionSegment>>,
+}
+
+/// Part of a transcription, `start` and `end` are in seconds
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TranscriptionSegment {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    #[default]
+    Mp3,
+    Wav,
+    Opus,
+}
+
+impl AudioFormat {
+    pub fn mime(&self) -> &'static str {
+        match self {
+            Self::Mp3 => "audio/mpeg",
+            Self::Wav => "audio/wav",
+            Self::Opus => "audio/ogg",
+        }
+    }
+}
+
+/**
+Result of `generate_audio`, depending on the model
+
+Synchronous models respond with the audio right away, either as raw bytes or as JSON holding a url
+or base64 data. Asynchronous ones start a job which is polled with `fetch_audio` or `wait_for_audio`
+until its url is set
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AudioGeneration {
+    /// Raw audio bytes from a synchronous model
+    Audio(Vec<u8>),
+    /// Url of the audio from a synchronous model answering with JSON
+    Url(String),
+    /// Base64 encoded audio from a synchronous model answering with JSON
+    Base64(String),
+    /// Id of the job started by an asynchronous model
+    Job(GenerationId),
+}
+
+impl AudioGeneration {
+    /// Reads a JSON response, it's only a job if it has an id
+    fn from_value(raw: Value) -> crate::Result<Self> {
+        if raw["id"].is_string() || raw["generation_id"].is_string() {
+            return Ok(Self::Job(Generation::from_value(raw)?.id));
+        }
+        let url = ["audio_file", "audio"]
+            .iter()
+            .find_map(|field| raw[field]["url"].as_str())
+            .or_else(|| raw["url"].as_str());
+        if let Some(url) = url {
+            return Ok(Self::Url(url.to_string()));
+        }
+        let data = ["audio_file", "audio"]
+            .iter()
+            .find_map(|field| raw[field]["data"].as_str())
+            .or_else(|| raw["audio"].as_str())
+            .or_else(|| raw["b64_json"].as_str());
+        match data {
+            Some(data) => Ok(Self::Base64(data.to_string())),
+            None => Err(Error::InvalidResponse(
+                "audio generation has neither an id nor audio".to_string(),
+            )),
+        }
+    }
+}
+
+/// Custom controls for audio generation, only some models support each of them
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct AudioGenerationParams {
+    /// Length in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u32>,
+    /// Style or genre like `lo-fi hip hop`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style: Option<String>,
+    /// Song lyrics, for models that sing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lyrics: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+/// Clip `extend_audio` continues
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AudioSource {
+    /// A completed earlier generation
+    Generation(GenerationId),
+    /// An audio file in mp3, wav, ogg, flac or m4a format
+    File { bytes: Vec<u8>, filename: String },
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    use super::*;
+    use crate::{mock::MockTransport, retry::RetryPolicy};
+
+    fn mock_client() -> (MockTransport, Client) {
+        let mock = MockTransport::new();
+        let client = Client::new("key")
+            .with_retry_policy(RetryPolicy::none())
+            .with_transport(mock.clone());
+        (mock, client)
+    }
+
+    fn push_json(mock: &MockTransport, body: Value) {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        mock.push_response_with_headers(200, headers, body.to_string());
+    }
+
+    async fn generate(client: &Client) -> crate::Result<AudioGeneration> {
+        let model = Model::from("stable-audio".to_string());
+        client
+            .generate_audio(
+                &model,
+                "rain on a tin roof",
+                &AudioGenerationParams::default(),
+            )
+            .await
+    }
+
+    #[tokio::test]
+    async fn responses_with_an_id_are_jobs() {
+        let (mock, client) = mock_client();
+        push_json(
+            &mock,
+            json!({ "generation_id": "gen-1", "status": "queued" }),
+        );
+
+        let res = generate(&client).await.unwrap();
+
+        assert_eq!(res, AudioGeneration::Job(GenerationId("gen-1".to_string())));
+    }
+
+    #[tokio::test]
+    async fn json_audio_without_an_id_is_returned_right_away() {
+        let (mock, client) = mock_client();
+        push_json(
+            &mock,
+            json!({ "audio_file": { "url": "https://cdn/a.mp3" } }),
+        );
+        push_json(&mock, json!({ "audio": { "data": "SUQz" } }));
+        push_json(&mock, json!({ "status": "completed" }));
+
+        assert_eq!(
+            generate(&client).await.unwrap(),
+            AudioGeneration::Url("https://cdn/a.mp3".to_string())
+        );
+        assert_eq!(
+            generate(&client).await.unwrap(),
+            AudioGeneration::Base64("SUQz".to_string())
+        );
+        assert!(matches!(
+            generate(&client).await,
+            Err(Error::InvalidResponse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn binary_responses_are_raw_audio() {
+        let (mock, client) = mock_client();
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("audio/mpeg"));
+        mock.push_response_with_headers(200, headers, b"ID3audio".to_vec());
+
+        let res = generate(&client).await.unwrap();
+
+        assert_eq!(res, AudioGeneration::Audio(b"ID3audio".to_vec()));
+    }
+}
@@ -0,0 +1,296 @@
+/*!
+Synchronous wrappers around the async API
+
+Every call blocks the current thread on an internal runtime, so none of them may be called from
+within an async context (that panics), use the async API there instead
+*/
+
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+
+use tokio::runtime::{Builder, Runtime};
+
+#[cfg(feature = "audio")]
+use crate::audio::{AudioFormat, Transcription, TranscriptionParams};
+#[cfg(feature = "image")]
+use crate::image::{GeneratedImage, ImageParams};
+use crate::{
+    chat::{Completion, CompletionParams, CompletionResponse, Logprobs, Usage},
+    model::{Model, ModelInfo},
+    retry::RetryPolicy,
+};
+
+/// Returns the assistant reply and adds it to history as first element if history is enabled
+pub fn send_completion(
+    api_key: &str,
+    model: &Model,
+    msg: Completion,
+    params: &CompletionParams,
+    history: &mut Option<VecDeque<Completion>>,
+) -> crate::Result<Completion> {
+    Client::new(api_key)?.send_completion(model, msg, params, history)
+}
+
+/**
+Retrieves all AIMLAPI models
+
+Will return an error if request fails
+*/
+pub fn get_models() -> crate::Result<Vec<ModelInfo>> {
+    // the models endpoint does not require an api key
+    Client::new(String::new())?.get_models()
+}
+
+/// Same as `get_models` but authorizes with the api key, for deployments requiring it on `/models`
+pub fn get_models_with_key(api_key: &str) -> crate::Result<Vec<ModelInfo>> {
+    Client::new(api_key)?.get_models()
+}
+
+/**
+Blocking counterpart of `crate::client::Client`
+
+Owns a single threaded runtime that drives the async client, clones share it
+*/
+#[derive(Debug, Clone)]
+pub struct Client {
+    inner: crate::client::Client,
+    runtime: Arc<Runtime>,
+}
+
+impl Client {
+    /// Fails if the runtime could not be created
+    pub fn new(api_key: impl Into<String>) -> crate::Result<Self> {
+        Self::from_async(crate::client::Client::new(api_key))
+    }
+
+    /// Wraps an already configured async client, fails if the runtime could not be created
+    pub fn from_async(inner: crate::client::Client) -> crate::Result<Self> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        Ok(Self {
+            inner,
+            runtime: Arc::new(runtime),
+        })
+    }
+
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.inner = self.inner.with_base_url(base_url);
+        self
+    }
+
+    pub fn with_keys(mut self, keys: Vec<String>) -> Self {
+        self.inner = self.inner.with_keys(keys);
+        self
+    }
+
+    pub fn with_key_cooldown(mut self, cooldown: Duration) -> Self {
+        self.inner = self.inner.with_key_cooldown(cooldown);
+        self
+    }
+
+    /// See `crate::client::Client::with_header`
+    pub fn with_header(mut self, name: &str, value: &str) -> crate::Result<Self> {
+        self.inner = self.inner.with_header(name, value)?;
+        Ok(self)
+    }
+
+    /// See `crate::client::Client::with_user_agent`
+    pub fn with_user_agent(mut self, user_agent: &str) -> crate::Result<Self> {
+        self.inner = self.inner.with_user_agent(user_agent)?;
+        Ok(self)
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.inner = self.inner.with_retry_policy(retry_policy);
+        self
+    }
+
+    pub fn with_max_response_size(mut self, bytes: usize) -> Self {
+        self.inner = self.inner.with_max_response_size(bytes);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.inner = self.inner.with_timeout(timeout);
+        self
+    }
+
+    /// Returns the wrapped async client
+    pub fn inner(&self) -> &crate::client::Client {
+        &self.inner
+    }
+
+    /// Returns the assistant reply and adds it to history as first element if history is enabled
+    pub fn send_completion(
+        &self,
+        model: &Model,
+        msg: Completion,
+        params: &CompletionParams,
+        history: &mut Option<VecDeque<Completion>>,
+    ) -> crate::Result<Completion> {
+        self.runtime
+            .block_on(self.inner.send_completion(model, msg, params, history))
+    }
+
+    /// See `crate::client::Client::send_batch`
+    pub fn send_batch(
+        &self,
+        requests: Vec<(Model, Completion, CompletionParams)>,
+        concurrency: usize,
+    ) -> Vec<crate::Result<Completion>> {
+        self.runtime
+            .block_on(self.inner.send_batch(requests, concurrency))
+    }
+
+    /// See `crate::client::Client::send_completion_choices`
+    pub fn send_completion_choices(
+        &self,
+        model: &Model,
+        msg: Completion,
+        params: &CompletionParams,
+        history: &mut Option<VecDeque<Completion>>,
+    ) -> crate::Result<Vec<Completion>> {
+        self.runtime.block_on(
+            self.inner
+                .send_completion_choices(model, msg, params, history),
+        )
+    }
+
+    /// See `crate::client::Client::send_completion_with_usage`
+    pub fn send_completion_with_usage(
+        &self,
+        model: &Model,
+        msg: Completion,
+        params: &CompletionParams,
+        history: &mut Option<VecDeque<Completion>>,
+    ) -> crate::Result<(Completion, Option<Usage>)> {
+        self.runtime.block_on(
+            self.inner
+                .send_completion_with_usage(model, msg, params, history),
+        )
+    }
+
+    /// See `crate::client::Client::send_completion_with_logprobs`
+    pub fn send_completion_with_logprobs(
+        &self,
+        model: &Model,
+        msg: Completion,
+        params: &CompletionParams,
+        history: &mut Option<VecDeque<Completion>>,
+    ) -> crate::Result<(Completion, Option<Logprobs>)> {
+        self.runtime.block_on(
+            self.inner
+                .send_completion_with_logprobs(model, msg, params, history),
+        )
+    }
+
+    /// See `crate::client::Client::send_completion_response`
+    pub fn send_completion_response(
+        &self,
+        model: &Model,
+        msg: Completion,
+        params: &CompletionParams,
+        history: &mut Option<VecDeque<Completion>>,
+    ) -> crate::Result<CompletionResponse> {
+        self.runtime.block_on(
+            self.inner
+                .send_completion_response(model, msg, params, history),
+        )
+    }
+
+    /// See `crate::client::Client::send_messages`
+    pub fn send_messages(
+        &self,
+        model: &Model,
+        messages: &[Completion],
+        params: &CompletionParams,
+    ) -> crate::Result<CompletionResponse> {
+        self.runtime
+            .block_on(self.inner.send_messages(model, messages, params))
+    }
+
+    /**
+    Retrieves all AIMLAPI models
+
+    Will return an error if request fails
+    */
+    pub fn get_models(&self) -> crate::Result<Vec<ModelInfo>> {
+        self.runtime.block_on(self.inner.get_models())
+    }
+
+    /// See `crate::client::Client::get_model`
+    pub fn get_model(&self, name: &str) -> crate::Result<Option<ModelInfo>> {
+        self.runtime.block_on(self.inner.get_model(name))
+    }
+
+    /**
+    Retrieves the names of all AIMLAPI models
+
+    Will return an error if request fails
+    */
+    pub fn model_names(&self) -> crate::Result<Vec<Model>> {
+        self.runtime.block_on(self.inner.model_names())
+    }
+
+    /// Returns the generated speech as raw audio bytes encoded in the provided format
+    #[cfg(feature = "audio")]
+    pub fn text_to_speech(
+        &self,
+        model: &Model,
+        text: &str,
+        voice: &str,
+        format: AudioFormat,
+    ) -> crate::Result<Vec<u8>> {
+        self.runtime
+            .block_on(self.inner.text_to_speech(model, text, voice, format))
+    }
+
+    /// Same as `text_to_speech` but writes the audio to `writer` as it arrives instead of returning it
+    #[cfg(feature = "audio")]
+    pub fn text_to_speech_to_writer(
+        &self,
+        model: &Model,
+        text: &str,
+        voice: &str,
+        format: AudioFormat,
+        writer: &mut impl std::io::Write,
+    ) -> crate::Result<u64> {
+        self.runtime.block_on(
+            self.inner
+                .text_to_speech_to_writer(model, text, voice, format, writer),
+        )
+    }
+
+    /**
+    Uploads audio and returns the recognized text
+
+    Fails if the audio is empty or the request failed
+    */
+    #[cfg(feature = "audio")]
+    pub fn transcribe(
+        &self,
+        model: &Model,
+        audio: Vec<u8>,
+        filename: &str,
+        params: &TranscriptionParams,
+    ) -> crate::Result<Transcription> {
+        self.runtime
+            .block_on(self.inner.transcribe(model, audio, filename, params))
+    }
+
+    /**
+    Edits an image according to the prompt, only the transparent areas of `mask` are changed
+
+    Fails if the image or mask is not a PNG or the request failed
+    */
+    #[cfg(feature = "image")]
+    pub fn edit_image(
+        &self,
+        model: &Model,
+        image: Vec<u8>,
+        mask: Option<Vec<u8>>,
+        prompt: &str,
+        params: &ImageParams,
+    ) -> crate::Result<Vec<GeneratedImage>> {
+        self.runtime
+            .block_on(self.inner.edit_image(model, image, mask, prompt, params))
+    }
+}
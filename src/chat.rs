@@ -1,114 +1,582 @@
-use std::collections::VecDeque;
+use std::{
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+    fmt,
+    ops::AddAssign,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
 
-use anyhow::anyhow;
-use reqwest::{
-    header::{HeaderMap, HeaderValue, AUTHORIZATION},
-    StatusCode,
+#[cfg(feature = "files")]
+use crate::files::FileId;
+use crate::{
+    client::{Client, ResponseMeta},
+    model::Model,
+    stream::CompletionStream,
+    tools::{Tool, ToolCall, ToolChoice},
+    Error,
 };
-use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
 
-use crate::{model::Model, BASE_API_URL};
+/**
+Sends a single user prompt with default params and returns the reply's text
 
-/// Returns Ok if message was sent successfully and adds response to history as first element
+Shortest way to get an answer, use `send_completion` or a `Client` for anything more involved
+*/
+pub async fn ask(api_key: &str, model_name: &str, prompt: &str) -> crate::Result<String> {
+    let model = Model::from(model_name.to_string());
+    let reply = Client::new(api_key)
+        .send_completion(
+            &model,
+            Completion::user(prompt),
+            &CompletionParams::default(),
+            &mut None,
+        )
+        .await?;
+    Ok(reply.content.text().into_owned())
+}
+
+/// Returns the assistant reply and adds it to history as first element if history is enabled
 pub async fn send_completion(
     api_key: &str,
     model: &Model,
     msg: Completion,
     params: &CompletionParams,
     history: &mut Option<VecDeque<Completion>>,
-) -> anyhow::Result<()> {
-    let client = reqwest::Client::new();
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        AUTHORIZATION,
-        HeaderValue::from_str(&format!("Bearer {api_key}")).unwrap(),
-    );
-
-    add_history(history, msg.clone());
-
-    let messages = if history.is_some() {
-        // if im correct i should remove ai messages from history
-        let mut history = history.as_ref().unwrap().clone();
-        history.retain(|msg| msg.get_role() != CompletionRole::AI);
-        json!(history)
-    } else {
-        let history: [Completion; 1] = [msg];
-        json!(history)
-    };
+) -> crate::Result<Completion> {
+    Client::new(api_key)
+        .send_completion(model, msg, params, history)
+        .await
+}
 
-    let json = json!({
-    "model": model.name,
-    "max_tokens": params.max_tokens,
-    "frequency_penalty": params.frequency_penalty,
-    "top_p": params.top_p,
-    "temperature": params.temperature,
-    "stream": params.stream,
-    "messages": messages,
-    });
-    let res = client
-        .post(BASE_API_URL.to_string() + "/chat/completions")
-        .headers(headers)
-        .json(&json)
-        .send()
-        .await?;
+/**
+Sends a completion and returns every choice the API responded with
 
-    if res.status() != StatusCode::CREATED {
-        return Err(anyhow!("request failed {}", res.status()));
-    }
+Only the first choice is added to history as first element
 
-    let res = res.text().await?;
-    let mut json: Value = serde_json::from_str(&res)?;
-    // TODO: return every choice
-    let message = json["choices"].take()[0].take()["message"].take()["content"].take();
+Fails if the request failed or the response contains no choices
+*/
+pub async fn send_completion_choices(
+    api_key: &str,
+    model: &Model,
+    msg: Completion,
+    params: &CompletionParams,
+    history: &mut Option<VecDeque<Completion>>,
+) -> crate::Result<Vec<Completion>> {
+    Client::new(api_key)
+        .send_completion_choices(model, msg, params, history)
+        .await
+}
 
-    if !message.is_string() {
-        return Err(anyhow!("message is not a string"));
-    }
+/**
+Sends a completion and returns the first choice along with the token usage
 
-    add_history(
-        history,
-        Completion::new(CompletionRole::AI, message.as_str().unwrap()),
-    );
+Usage is None if the API did not report it
+*/
+pub async fn send_completion_with_usage(
+    api_key: &str,
+    model: &Model,
+    msg: Completion,
+    params: &CompletionParams,
+    history: &mut Option<VecDeque<Completion>>,
+) -> crate::Result<(Completion, Option<Usage>)> {
+    Client::new(api_key)
+        .send_completion_with_usage(model, msg, params, history)
+        .await
+}
+
+/**
+Sends a completion with streaming enabled and returns a stream of content deltas
 
-    Ok(())
+The message is added to history as first element, the streamed response is not
+*/
+pub async fn send_completion_stream(
+    api_key: &str,
+    model: &Model,
+    msg: Completion,
+    params: &CompletionParams,
+    history: &mut Option<VecDeque<Completion>>,
+) -> crate::Result<CompletionStream> {
+    Client::new(api_key)
+        .send_completion_stream(model, msg, params, history)
+        .await
 }
 
-pub fn add_history(history: &mut Option<VecDeque<Completion>>, msg: Completion) {
-    if history.is_none() {
-        return;
-    }
+/// Adds a message to history as first element, returns false if history is disabled and nothing was stored
+pub fn add_history(history: &mut Option<VecDeque<Completion>>, msg: Completion) -> bool {
+    let Some(history) = history.as_mut() else {
+        return false;
+    };
 
-    history.as_mut().unwrap().push_front(msg);
+    history.push_front(msg);
+    true
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Completion {
-    role: String,
-    pub content: String,
+    role: CompletionRole,
+    // assistant messages that only call tools have a null content
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub content: Content,
+    /// Distinguishes multiple participants sharing the same role
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Tools the model requested to call, only set on assistant messages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<ToolCall>>,
+    /// Id of the tool call a tool message carries the result of
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
 }
 
 impl Completion {
     pub fn new(role: CompletionRole, content: &str) -> Self {
         Self {
-            role: role.into(),
-            content: content.to_string(),
+            role,
+            content: Content::from(content),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
+
+    /// Same as `new` with `CompletionRole::USER`
+    pub fn user(content: &str) -> Self {
+        Self::new(CompletionRole::USER, content)
+    }
+
+    /// Same as `new` with `CompletionRole::SYSTEM`
+    pub fn system(content: &str) -> Self {
+        Self::new(CompletionRole::SYSTEM, content)
+    }
+
+    /// Same as `new` with `CompletionRole::ASSISTANT`
+    pub fn assistant(content: &str) -> Self {
+        Self::new(CompletionRole::ASSISTANT, content)
+    }
+
+    pub fn new_named(role: CompletionRole, name: &str, content: &str) -> Self {
+        Self {
+            name: Some(name.to_string()),
+            ..Self::new(role, content)
+        }
+    }
+
+    /**
+    Creates a message with text and an attached image
+
+    `image_url` can be a regular url or a base64 data uri like `data:image/png;base64,...`
+    */
+    pub fn with_image(role: CompletionRole, text: &str, image_url: &str) -> Self {
+        Self {
+            content: Content::Parts(vec![
+                ContentPart::Text {
+                    text: text.to_string(),
+                },
+                ContentPart::ImageUrl {
+                    image_url: ImageUrl::new(image_url),
+                },
+            ]),
+            ..Self::new(role, "")
+        }
+    }
+
+    /**
+    Creates a message with text and an attached file uploaded with `Client::upload_file`
+
+    Models that don't read files reject the request
+    */
+    #[cfg(feature = "files")]
+    pub fn with_file(role: CompletionRole, text: &str, file_id: &FileId) -> Self {
+        Self {
+            content: Content::Parts(vec![
+                ContentPart::Text {
+                    text: text.to_string(),
+                },
+                ContentPart::File {
+                    file: FileRef::from(file_id),
+                },
+            ]),
+            ..Self::new(role, "")
+        }
+    }
+
+    /// Creates a tool message carrying the result of the tool call specified by `tool_call_id`
+    pub fn tool(tool_call_id: &str, content: &str) -> Self {
+        Self {
+            tool_call_id: Some(tool_call_id.to_string()),
+            ..Self::new(CompletionRole::TOOL, content)
         }
     }
 
     pub fn get_role(&self) -> CompletionRole {
-        CompletionRole::from(self.role.clone())
+        self.role
+    }
+
+    /// Returns if the content is empty or only whitespace and there are no tool calls
+    pub fn is_empty(&self) -> bool {
+        self.content.text().trim().is_empty() && self.tool_calls.as_ref().is_none_or(Vec::is_empty)
+    }
+
+    /**
+    Deserializes the content into a user type
+
+    Meant for responses requested with `ResponseFormat::JsonObject` or `ResponseFormat::JsonSchema`
+    */
+    pub fn parse_json<T: DeserializeOwned>(&self) -> crate::Result<T> {
+        Ok(serde_json::from_str(&self.content.text())?)
+    }
+}
+
+/// Creates a user message
+impl From<&str> for Completion {
+    fn from(content: &str) -> Self {
+        Self::user(content)
+    }
+}
+
+/**
+Content of a message, either plain text or multiple parts
+
+Plain text is serialized as a string, parts as an array of `{ "type": "text" }`, `{ "type": "image_url" }`
+and `{ "type": "file" }` objects
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Content {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+impl Content {
+    /// Returns the text of the content, text parts are joined by newlines, images and files are skipped
+    pub fn text(&self) -> Cow<'_, str> {
+        match self {
+            Self::Text(text) => Cow::Borrowed(text),
+            Self::Parts(parts) => Cow::Owned(
+                parts
+                    .iter()
+                    .filter_map(|part| match part {
+                        ContentPart::Text { text } => Some(text.as_str()),
+                        ContentPart::ImageUrl { .. } | ContentPart::File { .. } => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ),
+        }
+    }
+}
+
+impl Default for Content {
+    fn default() -> Self {
+        Self::Text(String::new())
+    }
+}
+
+impl From<&str> for Content {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_string())
+    }
+}
+
+impl From<String> for Content {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl fmt::Display for Content {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+    File { file: FileRef },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ImageUrl {
+    pub url: String,
+    /// Resolution the model processes the image at, `low`, `high` or `auto`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl ImageUrl {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            detail: None,
+        }
+    }
+}
+
+/// Reference to an uploaded file, see `Completion::with_file`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileRef {
+    pub file_id: String,
+}
+
+#[cfg(feature = "files")]
+impl From<&FileId> for FileRef {
+    fn from(value: &FileId) -> Self {
+        Self {
+            file_id: value.0.clone(),
+        }
+    }
+}
+
+/**
+Decides which messages of a history are sent, the newest message is always sent
+
+Messages not sent stay in history
+*/
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryPolicy {
+    /// Every message is sent
+    #[default]
+    Full,
+    /// Assistant replies and tool results are left out, only user and system messages are sent
+    DropAssistant,
+    /// Only the newest n messages are sent
+    LastN(usize),
+}
+
+impl HistoryPolicy {
+    /// Filters messages given in chronological order
+    pub fn apply<'a>(&self, mut messages: Vec<&'a Completion>) -> Vec<&'a Completion> {
+        let Some(newest) = messages.pop() else {
+            return messages;
+        };
+        match self {
+            Self::Full => {}
+            Self::DropAssistant => messages.retain(|msg| {
+                !matches!(msg.role, CompletionRole::ASSISTANT | CompletionRole::TOOL)
+            }),
+            Self::LastN(n) => {
+                let keep = n.saturating_sub(1);
+                messages.drain(..messages.len().saturating_sub(keep));
+            }
+        }
+        messages.push(newest);
+        messages
+    }
+}
+
+/// Parsed chat completion response
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CompletionResponse {
+    /// Id the API assigned to the completion, reference it when contacting support
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Seconds since the unix epoch the completion was created at
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<u64>,
+    /**
+    Backend configuration the completion was generated with, not every model reports it
+
+    Output for a fixed `seed` is only reproducible as long as the fingerprint stays the same
+    */
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_fingerprint: Option<String>,
+    pub choices: Vec<Choice>,
+    #[serde(default)]
+    pub usage: Option<Usage>,
+    /// Request id and rate limit headers, not part of the body
+    #[serde(skip)]
+    pub meta: ResponseMeta,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Choice {
+    pub message: Completion,
+    /// Why the model stopped generating, None if the API did not report it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<FinishReason>,
+    /// Log probabilities of the generated tokens, only present if requested through `CompletionParams::logprobs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<Logprobs>,
+}
+
+/// Log probabilities of the tokens of a choice
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Logprobs {
+    /// Tokens of the content in order, None if the API did not return any
+    #[serde(default)]
+    pub content: Option<Vec<TokenLogprob>>,
+}
+
+impl Logprobs {
+    /// Returns the tokens of the content, empty if the API did not return any
+    pub fn tokens(&self) -> &[TokenLogprob] {
+        self.content.as_deref().unwrap_or_default()
+    }
+}
+
+/// Generated token along with its log probability and the most likely alternatives
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f64,
+    /// UTF-8 bytes of the token, useful for tokens that split a character
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<Vec<u8>>,
+    /// Most likely tokens at this position, as many as `CompletionParams::top_logprobs`
+    #[serde(default)]
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+impl TokenLogprob {
+    /// Returns the probability between 0 and 1
+    pub fn probability(&self) -> f64 {
+        self.logprob.exp()
+    }
+}
+
+/// Alternative token at a position
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TopLogprob {
+    pub token: String,
+    pub logprob: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<Vec<u8>>,
+}
+
+/**
+Reason the model stopped generating a choice
+
+`Length` means the output was truncated, raise `max_tokens` or continue the response
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(from = "String", into = "String")]
+pub enum FinishReason {
+    /// Natural end of the message or a stop sequence was hit
+    Stop,
+    /// `max_tokens` or the context length was reached
+    Length,
+    /// The model called tools
+    ToolCalls,
+    /// Content was omitted by the content filter
+    ContentFilter,
+    /// Any reason not known to this crate
+    Other(String),
+}
+
+impl From<String> for FinishReason {
+    fn from(value: String) -> Self {
+        match value.as_str() {
+            "stop" => Self::Stop,
+            "length" => Self::Length,
+            "tool_calls" => Self::ToolCalls,
+            "content_filter" => Self::ContentFilter,
+            _ => Self::Other(value),
+        }
+    }
+}
+
+impl From<FinishReason> for String {
+    fn from(value: FinishReason) -> Self {
+        match value {
+            FinishReason::Stop => "stop".to_string(),
+            FinishReason::Length => "length".to_string(),
+            FinishReason::ToolCalls => "tool_calls".to_string(),
+            FinishReason::ContentFilter => "content_filter".to_string(),
+            FinishReason::Other(reason) => reason,
+        }
+    }
+}
+
+/// Token usage reported by the API for a single request
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct Usage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, other: Self) {
+        self.prompt_tokens = self.prompt_tokens.saturating_add(other.prompt_tokens);
+        self.completion_tokens = self
+            .completion_tokens
+            .saturating_add(other.completion_tokens);
+        self.total_tokens = self.total_tokens.saturating_add(other.total_tokens);
     }
 }
 
 // change f32 to f16 when it's available in stable release
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CompletionParams {
     pub max_tokens: u32,
     pub frequency_penalty: f32,
     pub top_p: f32,
     pub temperature: f32,
     pub stream: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    /// Sequences where the API will stop generating further tokens
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    /// Seed for reproducible output, supported models sample deterministically for the same seed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    /// Bias added to the logits of token ids, within -100 and 100
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logit_bias: Option<HashMap<u32, f32>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<Tool>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<ToolChoice>,
+    /// Whether the model may call several tools in one turn, `false` limits it to at most one call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parallel_tool_calls: Option<bool>,
+    /// Identifies the end-user for abuse monitoring, never included in tracing output
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    /// Returns the log probabilities of the generated tokens in `Choice::logprobs`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub logprobs: bool,
+    /// How many of the most likely tokens to return per position, within 0 and 20, requires `logprobs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_logprobs: Option<u8>,
+    /// How many choices to generate, only sent when above 1 and can't be combined with streaming
+    #[serde(default, skip_serializing_if = "is_single_choice")]
+    pub n: Option<u32>,
+    /**
+    Asks streams to report token usage in a last chunk, see `CompletionStream::usage`
+
+    Off by default since not every backend accepts the `stream_options` this sends, ignored when not streaming
+    */
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stream_usage: bool,
+}
+
+fn is_single_choice(n: &Option<u32>) -> bool {
+    n.is_none_or(|n| n <= 1)
+}
+
+/**
+Format the model must respond in
+
+`JsonSchema` is serialized as `{ "type": "json_schema", "json_schema": schema }`
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    Text,
+    JsonObject,
+    JsonSchema {
+        #[serde(rename = "json_schema")]
+        schema: Value,
+    },
 }
 
 impl CompletionParams {
@@ -125,33 +593,245 @@ impl CompletionParams {
             top_p,
             temperature,
             stream,
+            presence_penalty: None,
+            stop: None,
+            seed: None,
+            response_format: None,
+            logit_bias: None,
+            tools: None,
+            tool_choice: None,
+            parallel_tool_calls: None,
+            user: None,
+            logprobs: false,
+            top_logprobs: None,
+            n: None,
+            stream_usage: false,
         }
     }
+
+    pub fn builder() -> CompletionParamsBuilder {
+        CompletionParamsBuilder::default()
+    }
+
+    /// Fails if any parameter is outside of the range the API accepts
+    pub fn validate(&self) -> crate::Result<()> {
+        if let Some(logit_bias) = &self.logit_bias {
+            if let Some((token, bias)) = logit_bias
+                .iter()
+                .find(|(_, bias)| !(-100.0..=100.0).contains(*bias))
+            {
+                return Err(Error::InvalidInput(format!(
+                    "logit bias {bias} for token {token} is outside of -100 and 100"
+                )));
+            }
+        }
+        if self.stream && !is_single_choice(&self.n) {
+            return Err(Error::InvalidInput(
+                "several choices can't be streamed".to_string(),
+            ));
+        }
+        if let Some(top_logprobs) = self.top_logprobs {
+            if top_logprobs > 20 {
+                return Err(Error::InvalidInput(format!(
+                    "top logprobs {top_logprobs} is outside of 0 and 20"
+                )));
+            }
+            if !self.logprobs {
+                return Err(Error::InvalidInput(
+                    "top logprobs requires logprobs to be enabled".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for CompletionParams {
+    fn default() -> Self {
+        Self::new(512, 0.7, 0.7, 0.7, false)
+    }
+}
+
+/// Builder for `CompletionParams`, every knob that isn't set keeps its default value
+#[derive(Debug, Default, Clone)]
+pub struct CompletionParamsBuilder {
+    params: CompletionParams,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+impl CompletionParamsBuilder {
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.params.max_tokens = max_tokens;
+        self
+    }
+
+    pub fn frequency_penalty(mut self, frequency_penalty: f32) -> Self {
+        self.params.frequency_penalty = frequency_penalty;
+        self
+    }
+
+    pub fn top_p(mut self, top_p: f32) -> Self {
+        self.params.top_p = top_p;
+        self
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.params.temperature = temperature;
+        self
+    }
+
+    pub fn stream(mut self, stream: bool) -> Self {
+        self.params.stream = stream;
+        self
+    }
+
+    pub fn presence_penalty(mut self, presence_penalty: f32) -> Self {
+        self.params.presence_penalty = Some(presence_penalty);
+        self
+    }
+
+    pub fn stop(mut self, stop: Vec<String>) -> Self {
+        self.params.stop = Some(stop);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.params.seed = Some(seed);
+        self
+    }
+
+    pub fn response_format(mut self, response_format: ResponseFormat) -> Self {
+        self.params.response_format = Some(response_format);
+        self
+    }
+
+    pub fn tools(mut self, tools: Vec<Tool>) -> Self {
+        self.params.tools = Some(tools);
+        self
+    }
+
+    pub fn tool_choice(mut self, tool_choice: ToolChoice) -> Self {
+        self.params.tool_choice = Some(tool_choice);
+        self
+    }
+
+    pub fn parallel_tool_calls(mut self, parallel_tool_calls: bool) -> Self {
+        self.params.parallel_tool_calls = Some(parallel_tool_calls);
+        self
+    }
+
+    pub fn logit_bias(mut self, logit_bias: HashMap<u32, f32>) -> Self {
+        self.params.logit_bias = Some(logit_bias);
+        self
+    }
+
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.params.user = Some(user.into());
+        self
+    }
+
+    pub fn logprobs(mut self, logprobs: bool) -> Self {
+        self.params.logprobs = logprobs;
+        self
+    }
+
+    /// Also enables `logprobs`
+    pub fn top_logprobs(mut self, top_logprobs: u8) -> Self {
+        self.params.logprobs = true;
+        self.params.top_logprobs = Some(top_logprobs);
+        self
+    }
+
+    /// Asks for several choices, see `Chat::send_completion_with_alternatives`
+    pub fn n(mut self, n: u32) -> Self {
+        self.params.n = Some(n);
+        self
+    }
+
+    pub fn stream_usage(mut self, stream_usage: bool) -> Self {
+        self.params.stream_usage = stream_usage;
+        self
+    }
+
+    /// Fails if any parameter is outside of the range the API accepts
+    pub fn build(self) -> crate::Result<CompletionParams> {
+        self.params.validate()?;
+        Ok(self.params)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
 pub enum CompletionRole {
+    #[serde(alias = "USER")]
     USER,
+    #[serde(alias = "SYSTEM")]
     SYSTEM,
-    AI,
+    // histories saved by earlier versions stored assistant messages as "placeholder"
+    #[serde(
+        rename = "assistant",
+        alias = "placeholder",
+        alias = "AI",
+        alias = "ai"
+    )]
+    ASSISTANT,
+    TOOL,
 }
 
-impl From<String> for CompletionRole {
-    fn from(value: String) -> Self {
-        match value.as_str() {
-            "user" => Self::USER,
-            "system" => Self::SYSTEM,
-            _ => Self::AI,
-        }
-    }
+impl CompletionRole {
+    #[deprecated(note = "use `CompletionRole::ASSISTANT` instead")]
+    pub const AI: CompletionRole = CompletionRole::ASSISTANT;
 }
 
-impl From<CompletionRole> for String {
-    fn from(value: CompletionRole) -> Self {
-        match value {
-            CompletionRole::USER => "user".to_string(),
-            CompletionRole::SYSTEM => "system".to_string(),
-            CompletionRole::AI => "placeholder".to_string(),
-        }
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn roles_saved_by_earlier_versions_still_load() {
+        let history: Vec<Completion> = serde_json::from_value(json!([
+            { "role": "user", "content": "hello" },
+            { "role": "placeholder", "content": "hi" },
+            { "role": "system", "content": "be brief" },
+            { "role": "AI", "content": "hi again" },
+        ]))
+        .unwrap();
+
+        let roles: Vec<CompletionRole> = history.iter().map(Completion::get_role).collect();
+        assert_eq!(
+            roles,
+            [
+                CompletionRole::USER,
+                CompletionRole::ASSISTANT,
+                CompletionRole::SYSTEM,
+                CompletionRole::ASSISTANT,
+            ]
+        );
+        assert_eq!(json!(history[1])["role"], "assistant");
+    }
+
+    #[cfg(feature = "files")]
+    #[test]
+    fn file_parts_reference_the_uploaded_file() {
+        let file_id = FileId::from("file-abc123".to_string());
+        let msg = Completion::with_file(CompletionRole::USER, "summarize this", &file_id);
+
+        assert_eq!(
+            json!(msg)["content"],
+            json!([
+                { "type": "text", "text": "summarize this" },
+                { "type": "file", "file": { "file_id": "file-abc123" } },
+            ])
+        );
+        assert_eq!(msg.content.text(), "summarize this");
     }
 }
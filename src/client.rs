@@ -0,0 +1,1248 @@
+use std::{collections::VecDeque, fmt, sync::Arc, time::Duration};
+
+use futures_util::{stream, StreamExt};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, USER_AGENT},
+    Certificate, Method, Proxy, Request, RequestBuilder, Response,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    chat::{
+        add_history, Completion, CompletionParams, CompletionResponse, HistoryPolicy, Logprobs,
+        Usage,
+    },
+    keys::{self, KeyPool},
+    model::{self, Model, ModelInfo, ModelsResponse, ParamStyle},
+    recorder::{RecordedExchange, RequestRecorder},
+    retry::{self, RetryPolicy},
+    stream::{CompletionStream, StreamEvent},
+    transport::HttpTransport,
+    Error, BASE_API_URL,
+};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+const DEFAULT_USER_AGENT: &str = concat!("aimlapi-rs/", env!("CARGO_PKG_VERSION"));
+
+/**
+Reusable AIMLAPI client
+
+Holds a pooled `reqwest::Client`, so it should be created once and shared across requests
+*/
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    /// Set by `with_http_client`, such a client is never rebuilt
+    custom_http: bool,
+    /// None sends requests with `http`
+    transport: Option<Arc<dyn HttpTransport>>,
+    proxies: Vec<Proxy>,
+    root_certificates: Vec<Certificate>,
+    keys: Arc<KeyPool>,
+    base_url: String,
+    retry_policy: RetryPolicy,
+    timeout: Duration,
+    response_hook: Option<ResponseHook>,
+    headers: HeaderMap,
+    history_policy: HistoryPolicy,
+    max_response_size: Option<usize>,
+    recorder: Option<RequestRecorder>,
+}
+
+/// Callback receiving the endpoint path and raw body of every successful response before it's parsed
+pub type ResponseHook = Arc<dyn Fn(&str, &str) + Send + Sync>;
+
+/**
+Selected headers of a response, useful for support tickets and pacing requests before hitting `429`
+
+Every field is None if the API did not send the header
+*/
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ResponseMeta {
+    /// `x-request-id`
+    pub request_id: Option<String>,
+    /// `x-ratelimit-limit-requests`
+    pub ratelimit_limit_requests: Option<u64>,
+    /// `x-ratelimit-remaining-requests`
+    pub ratelimit_remaining_requests: Option<u64>,
+    /// `x-ratelimit-limit-tokens`
+    pub ratelimit_limit_tokens: Option<u64>,
+    /// `x-ratelimit-remaining-tokens`
+    pub ratelimit_remaining_tokens: Option<u64>,
+    /// `x-ratelimit-reset-requests`, usually a duration like `1s` or `6m0s`
+    pub ratelimit_reset_requests: Option<String>,
+}
+
+impl ResponseMeta {
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Self {
+        let text = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let number = |name: &str| text(name).and_then(|value| value.trim().parse().ok());
+        Self {
+            request_id: text("x-request-id"),
+            ratelimit_limit_requests: number("x-ratelimit-limit-requests"),
+            ratelimit_remaining_requests: number("x-ratelimit-remaining-requests"),
+            ratelimit_limit_tokens: number("x-ratelimit-limit-tokens"),
+            ratelimit_remaining_tokens: number("x-ratelimit-remaining-tokens"),
+            ratelimit_reset_requests: text("x-ratelimit-reset-requests"),
+        }
+    }
+}
+
+impl fmt::Debug for Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("keys", &format_args!("<{} redacted>", self.keys.len()))
+            .field("base_url", &self.base_url)
+            .field("retry_policy", &self.retry_policy)
+            .field("timeout", &self.timeout)
+            .field("response_hook", &self.response_hook.is_some())
+            .field("headers", &self.headers.keys().collect::<Vec<_>>())
+            .field("history_policy", &self.history_policy)
+            .field("max_response_size", &self.max_response_size)
+            .field("recorder", &self.recorder.is_some())
+            .finish()
+    }
+}
+
+impl Client {
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            custom_http: false,
+            transport: None,
+            proxies: Vec::new(),
+            root_certificates: Vec::new(),
+            keys: Arc::new(KeyPool::new(vec![api_key.into()], keys::DEFAULT_COOLDOWN)),
+            base_url: BASE_API_URL.to_string(),
+            retry_policy: RetryPolicy::default(),
+            timeout: DEFAULT_TIMEOUT,
+            response_hook: None,
+            headers: HeaderMap::from_iter([(
+                USER_AGENT,
+                HeaderValue::from_static(DEFAULT_USER_AGENT),
+            )]),
+            history_policy: HistoryPolicy::default(),
+            max_response_size: None,
+            recorder: None,
+        }
+    }
+
+    /**
+    Spreads requests over several api keys, replacing the current ones
+
+    Keys are rotated round-robin per request, a key answered with `429` or `402` is benched
+    for the cooldown period and the request is retried right away with the next available key,
+    independent of the retry policy
+
+    Requests fail with `Error::InvalidApiKey` if `keys` is empty
+    */
+    pub fn with_keys(mut self, keys: Vec<String>) -> Self {
+        self.keys = Arc::new(KeyPool::new(keys, self.keys.cooldown()));
+        self
+    }
+
+    /// Overrides how long a key that hit its quota is skipped, defaults to 60 seconds
+    pub fn with_key_cooldown(mut self, cooldown: Duration) -> Self {
+        self.keys = Arc::new(KeyPool::new(self.keys.keys().to_vec(), cooldown));
+        self
+    }
+
+    /**
+    Sets a callback invoked with the endpoint path and raw body of every successful response before it's parsed
+
+    Meant for debugging unexpected response shapes, the callback never receives the api key
+    */
+    pub fn with_response_hook(mut self, hook: impl Fn(&str, &str) + Send + Sync + 'static) -> Self {
+        self.response_hook = Some(Arc::new(hook));
+        self
+    }
+
+    /// Records every completion request and its response for audits, see `RequestRecorder`
+    pub fn with_recorder(mut self, recorder: RequestRecorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /**
+    Overrides how long a request may take before failing with `Error::Timeout`, defaults to 60 seconds
+
+    For streamed completions it bounds the wait for the response and the gap between chunks
+    rather than the whole stream
+    */
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Overrides how requests failing with `429` or `5xx` are retried, `RetryPolicy::none()` disables retries
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /**
+    Adds a header sent with every request, e.g. `X-Title` or `HTTP-Referer` required by some gateways
+
+    Setting the same header again replaces it, authorization is always set from the api keys
+
+    Fails if the name or value is not a valid header
+    */
+    pub fn with_header(mut self, name: &str, value: &str) -> crate::Result<Self> {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| Error::InvalidInput(format!("invalid header name {name}")))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|_| Error::InvalidInput(format!("invalid value for header {name}")))?;
+        self.headers.insert(name, value);
+        Ok(self)
+    }
+
+    /**
+    Replaces the `User-Agent` sent with every request, defaults to `aimlapi-rs/<version>`
+
+    Fails if the value is not a valid header value
+    */
+    pub fn with_user_agent(self, user_agent: &str) -> crate::Result<Self> {
+        self.with_header(USER_AGENT.as_str(), user_agent)
+    }
+
+    /**
+    Caps how many bytes of a response body are read before failing with `Error::ResponseTooLarge`
+
+    For streamed completions it caps the accumulated content instead, unlimited by default
+    */
+    pub fn with_max_response_size(mut self, bytes: usize) -> Self {
+        self.max_response_size = Some(bytes);
+        self
+    }
+
+    /// Decides which history messages are sent with completions, defaults to `HistoryPolicy::Full`
+    pub fn with_history_policy(mut self, history_policy: HistoryPolicy) -> Self {
+        self.history_policy = history_policy;
+        self
+    }
+
+    /**
+    Replaces how requests are sent, defaults to the client's own `reqwest::Client`
+
+    Retries, timeouts and status handling still apply, useful for tests or custom routing
+    */
+    pub fn with_transport(mut self, transport: impl HttpTransport + 'static) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /**
+    Replaces the underlying `reqwest::Client`, for any setting not covered by the other builders
+
+    Proxies and root certificates added before are dropped and adding them afterwards fails,
+    configure them on the provided client instead
+    */
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self.custom_http = true;
+        self.proxies.clear();
+        self.root_certificates.clear();
+        self
+    }
+
+    /**
+    Routes requests through a proxy, e.g. `reqwest::Proxy::https("http://proxy:8080")`
+
+    Without any proxy the `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` environment variables are
+    honored, adding one replaces them
+
+    Fails if the underlying client could not be built or was provided with `with_http_client`
+    */
+    pub fn with_proxy(mut self, proxy: Proxy) -> crate::Result<Self> {
+        self.proxies.push(proxy);
+        self.rebuild_http()?;
+        Ok(self)
+    }
+
+    /**
+    Trusts an additional root certificate, e.g. a corporate CA
+
+    Fails if the underlying client could not be built or was provided with `with_http_client`
+    */
+    pub fn with_root_certificate(mut self, certificate: Certificate) -> crate::Result<Self> {
+        self.root_certificates.push(certificate);
+        self.rebuild_http()?;
+        Ok(self)
+    }
+
+    fn rebuild_http(&mut self) -> crate::Result<()> {
+        // rebuilding would silently drop every setting of the provided client
+        if self.custom_http {
+            return Err(Error::InvalidInput(
+                "can't reconfigure a client set with with_http_client, configure it directly"
+                    .to_string(),
+            ));
+        }
+        let mut builder = reqwest::Client::builder();
+        for proxy in &self.proxies {
+            builder = builder.proxy(proxy.clone());
+        }
+        for certificate in &self.root_certificates {
+            builder = builder.add_root_certificate(certificate.clone());
+        }
+        self.http = builder.build()?;
+        Ok(())
+    }
+
+    fn transport(&self) -> &dyn HttpTransport {
+        self.transport.as_deref().unwrap_or(&self.http)
+    }
+
+    /**
+    Overrides the base url requests are sent to, defaults to `BASE_API_URL`
+
+    Useful for proxies, compatible gateways or mock servers, trailing slashes are ignored
+    */
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Joins the base url with an endpoint path like `/chat/completions`
+    pub(crate) fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url)
+    }
+
+    /**
+    Picks the next key from the pool and sets it as authorization, empty keys are not sent
+
+    Fails with `Error::InvalidApiKey` if the pool is empty or the key can't form a header value
+    */
+    fn authorize(&self, request: &mut Request) -> crate::Result<usize> {
+        let index = self.keys.pick().ok_or(Error::InvalidApiKey)?;
+        let key = self.keys.key(index);
+        if !key.is_empty() {
+            let mut value = HeaderValue::from_str(&format!("Bearer {key}"))
+                .map_err(|_| Error::InvalidApiKey)?;
+            value.set_sensitive(true);
+            request.headers_mut().insert(AUTHORIZATION, value);
+        }
+        Ok(index)
+    }
+
+    /// Starts a request to an endpoint path with the extra headers, authorization is added when it's sent
+    pub(crate) fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        self.http
+            .request(method, self.url(path))
+            .headers(self.headers.clone())
+    }
+
+    /**
+    Sends a request and turns non success statuses into errors
+
+    Requests failing with `429` or `5xx` are retried according to the retry policy,
+    a key that hit its quota is switched for the next one in the pool,
+    requests with streamed bodies like multipart uploads can't be cloned and are sent once
+    */
+    pub(crate) async fn execute(&self, req: RequestBuilder) -> crate::Result<Response> {
+        self.send(req, true).await
+    }
+
+    /// Same as `execute` but the timeout only applies until the response arrives, not to the body
+    pub(crate) async fn execute_stream(&self, req: RequestBuilder) -> crate::Result<Response> {
+        self.send(req, false).await
+    }
+
+    async fn send(&self, req: RequestBuilder, timeout_body: bool) -> crate::Result<Response> {
+        let mut request = req.build()?;
+        if timeout_body && request.timeout().is_none() {
+            *request.timeout_mut() = Some(self.timeout);
+        }
+        #[cfg(feature = "tracing")]
+        let path = request.url().path().to_string();
+
+        let mut attempts = 1;
+        loop {
+            let retry = if attempts <= self.retry_policy.max_retries || self.keys.len() > 1 {
+                request.try_clone()
+            } else {
+                None
+            };
+            let key = self.authorize(&mut request).map_err(failed)?;
+
+            #[cfg(feature = "tracing")]
+            let started = std::time::Instant::now();
+            let res =
+                match tokio::time::timeout(self.timeout, self.transport().execute(request)).await {
+                    Ok(Ok(res)) => res,
+                    Ok(Err(err)) => return Err(failed(Error::with_attempts(err, attempts))),
+                    Err(_) => return Err(failed(Error::with_attempts(Error::Timeout, attempts))),
+                };
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                path,
+                status = res.status().as_u16(),
+                latency_ms = started.elapsed().as_millis() as u64,
+                attempt = attempts,
+                "received response"
+            );
+
+            let status = res.status();
+            let retryable = RetryPolicy::is_retryable(status);
+            let quota = keys::is_quota_error(status);
+            if !retryable && !quota {
+                return check_status(res)
+                    .await
+                    .map_err(|err| failed(Error::with_attempts(err, attempts)));
+            }
+
+            let retry_after = retry::retry_after(res.headers());
+            let err = status_error(res).await;
+            // only switch keys if there is one left that isn't benched
+            let switched = quota && self.keys.len() > 1 && self.keys.bench(key);
+            let next = retry
+                .filter(|_| switched || (retryable && attempts <= self.retry_policy.max_retries));
+            let Some(next) = next else {
+                return Err(failed(Error::with_attempts(err, attempts)));
+            };
+
+            let delay = if switched {
+                Duration::ZERO
+            } else {
+                self.retry_policy.delay(attempts, retry_after)
+            };
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                path,
+                attempt = attempts,
+                switched_key = switched,
+                delay_ms = delay.as_millis() as u64,
+                "retrying request: {err}"
+            );
+            tokio::time::sleep(delay).await;
+            request = next;
+            attempts += 1;
+        }
+    }
+
+    /// Returns the assistant reply and adds it to history as first element if history is enabled
+    pub async fn send_completion(
+        &self,
+        model: &Model,
+        msg: Completion,
+        params: &CompletionParams,
+        history: &mut Option<VecDeque<Completion>>,
+    ) -> crate::Result<Completion> {
+        let res = self
+            .send_completion_response(model, msg, params, history)
+            .await?;
+        let choice = res.choices.into_iter().next().ok_or(Error::EmptyChoices)?;
+        Ok(choice.message)
+    }
+
+    /**
+    Sends independent one-shot completions concurrently, at most `concurrency` at a time
+
+    Results are returned in the order of `requests`, a failed request doesn't affect the others
+    */
+    pub async fn send_batch(
+        &self,
+        requests: Vec<(Model, Completion, CompletionParams)>,
+        concurrency: usize,
+    ) -> Vec<crate::Result<Completion>> {
+        let mut results: Vec<_> = stream::iter(requests.into_iter().enumerate())
+            .map(|(index, (model, msg, params))| async move {
+                let res = self.send_completion(&model, msg, &params, &mut None).await;
+                (index, res)
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await;
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, res)| res).collect()
+    }
+
+    /**
+    Sends a completion that is aborted once the token is cancelled
+
+    If the request was already started the message stays in history, the in-flight request is
+    dropped which closes its connection instead of returning it to the pool
+
+    Fails with `Error::Cancelled` if the token was cancelled before the response arrived
+    */
+    pub async fn send_completion_cancellable(
+        &self,
+        model: &Model,
+        msg: Completion,
+        params: &CompletionParams,
+        history: &mut Option<VecDeque<Completion>>,
+        token: &CancellationToken,
+    ) -> crate::Result<Completion> {
+        tokio::select! {
+            res = self.send_completion(model, msg, params, history) => res,
+            _ = token.cancelled() => Err(Error::Cancelled),
+        }
+    }
+
+    /**
+    Sends a completion and returns every choice the API responded with
+
+    Only the first choice is added to history as first element
+
+    Fails if the request failed or the response contains no choices
+    */
+    pub async fn send_completion_choices(
+        &self,
+        model: &Model,
+        msg: Completion,
+        params: &CompletionParams,
+        history: &mut Option<VecDeque<Completion>>,
+    ) -> crate::Result<Vec<Completion>> {
+        let res = self
+            .send_completion_response(model, msg, params, history)
+            .await?;
+        Ok(res
+            .choices
+            .into_iter()
+            .map(|choice| choice.message)
+            .collect())
+    }
+
+    /**
+    Sends a completion and returns the first choice along with the token usage
+
+    Usage is None if the API did not report it
+    */
+    pub async fn send_completion_with_usage(
+        &self,
+        model: &Model,
+        msg: Completion,
+        params: &CompletionParams,
+        history: &mut Option<VecDeque<Completion>>,
+    ) -> crate::Result<(Completion, Option<Usage>)> {
+        let res = self
+            .send_completion_response(model, msg, params, history)
+            .await?;
+        let usage = res.usage;
+        let choice = res.choices.into_iter().next().ok_or(Error::EmptyChoices)?;
+        Ok((choice.message, usage))
+    }
+
+    /**
+    Sends a completion and returns the first choice along with the log probabilities of its tokens
+
+    Logprobs are None unless enabled through `CompletionParams::logprobs` and supported by the model
+    */
+    pub async fn send_completion_with_logprobs(
+        &self,
+        model: &Model,
+        msg: Completion,
+        params: &CompletionParams,
+        history: &mut Option<VecDeque<Completion>>,
+    ) -> crate::Result<(Completion, Option<Logprobs>)> {
+        let res = self
+            .send_completion_response(model, msg, params, history)
+            .await?;
+        let choice = res.choices.into_iter().next().ok_or(Error::EmptyChoices)?;
+        Ok((choice.message, choice.logprobs))
+    }
+
+    /**
+    Sends a completion and returns the parsed response
+
+    Only the first choice is added to history as first element
+
+    Fails if the request failed or the response contains no choices
+    */
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(
+                model = %model.name,
+                max_tokens = params.max_tokens,
+                temperature = params.temperature,
+                stream = params.stream
+            )
+        )
+    )]
+    pub async fn send_completion_response(
+        &self,
+        model: &Model,
+        msg: Completion,
+        params: &CompletionParams,
+        history: &mut Option<VecDeque<Completion>>,
+    ) -> crate::Result<CompletionResponse> {
+        reject_stream(params)?;
+        add_history(history, msg.clone());
+
+        let messages = self.history_policy.apply(history_messages(&msg, history));
+        let json = completion_body(model, messages, params);
+        let res = self.post_completion(&json).await?;
+
+        let message = &res.choices.first().ok_or(Error::EmptyChoices)?.message;
+        add_history(history, message.clone());
+
+        Ok(res)
+    }
+
+    /**
+    Returns the exact JSON body `send_completion` would send for these arguments, without sending it
+
+    History is left untouched, the body contains it as if `msg` had been added
+    */
+    pub fn build_request_body(
+        &self,
+        model: &Model,
+        msg: &Completion,
+        params: &CompletionParams,
+        history: &Option<VecDeque<Completion>>,
+    ) -> Value {
+        let mut history = history.clone();
+        add_history(&mut history, msg.clone());
+        let messages = self.history_policy.apply(history_messages(msg, &history));
+        completion_body(model, messages, params)
+    }
+
+    /**
+    Sends the provided messages as they are, without touching any history
+
+    Messages are expected in chronological order
+
+    Fails if the request failed or the response contains no choices
+    */
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(
+                model = %model.name,
+                max_tokens = params.max_tokens,
+                temperature = params.temperature,
+                stream = params.stream
+            )
+        )
+    )]
+    pub async fn send_messages(
+        &self,
+        model: &Model,
+        messages: &[Completion],
+        params: &CompletionParams,
+    ) -> crate::Result<CompletionResponse> {
+        reject_stream(params)?;
+        let json = completion_body(model, messages.iter().collect(), params);
+        self.post_completion(&json).await
+    }
+
+    /// Reads a response body as text and passes it to the response hook
+    pub(crate) async fn read_text(&self, path: &str, res: Response) -> crate::Result<String> {
+        let body = self.read_bytes(res).await?;
+        let text = String::from_utf8_lossy(&body).into_owned();
+        if let Some(hook) = &self.response_hook {
+            hook(path, &text);
+        }
+        Ok(text)
+    }
+
+    /// Reads a response body, failing once it exceeds the max response size
+    pub(crate) async fn read_bytes(&self, res: Response) -> crate::Result<Vec<u8>> {
+        let Some(limit) = self.max_response_size else {
+            return Ok(res.bytes().await?.to_vec());
+        };
+        if res.content_length().is_some_and(|len| len > limit as u64) {
+            return Err(Error::ResponseTooLarge { limit });
+        }
+
+        let mut body = Vec::new();
+        let mut chunks = res.bytes_stream();
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk?;
+            if body.len() + chunk.len() > limit {
+                return Err(Error::ResponseTooLarge { limit });
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+
+    /**
+    Writes a response body to `writer` chunk by chunk as it arrives and returns the number of bytes written
+
+    Nothing is buffered so the max response size does not apply, the timeout applies to the gap between chunks
+    */
+    #[cfg(feature = "audio")]
+    pub(crate) async fn write_body(
+        &self,
+        res: Response,
+        writer: &mut impl std::io::Write,
+    ) -> crate::Result<u64> {
+        let mut written = 0;
+        let mut chunks = res.bytes_stream();
+        loop {
+            let chunk = tokio::time::timeout(self.timeout, chunks.next())
+                .await
+                .map_err(|_| Error::Timeout)?;
+            let Some(chunk) = chunk else {
+                break;
+            };
+            let chunk = chunk?;
+            writer.write_all(&chunk)?;
+            written += chunk.len() as u64;
+        }
+        writer.flush()?;
+        Ok(written)
+    }
+
+    async fn post_completion(&self, json: &Value) -> crate::Result<CompletionResponse> {
+        let res = self.post_completion_text(json).await;
+        match &res {
+            Ok((_, text)) => self.record(json, Some(text), None),
+            Err(err) => self.record(json, None, Some(err)),
+        }
+        let (meta, res) = res?;
+        let mut res: CompletionResponse = serde_json::from_str(&res)?;
+        if res.choices.is_empty() {
+            return Err(Error::EmptyChoices);
+        }
+        // replies with only tool calls are fine, they usually have no content
+        if res.choices[0].message.is_empty() {
+            return Err(Error::EmptyContent);
+        }
+        res.meta = meta;
+        Ok(res)
+    }
+
+    async fn post_completion_text(&self, json: &Value) -> crate::Result<(ResponseMeta, String)> {
+        let req = self.request(Method::POST, "/chat/completions").json(json);
+        let res = self.execute(req).await?;
+        let meta = ResponseMeta::from_headers(res.headers());
+        Ok((meta, self.read_text("/chat/completions", res).await?))
+    }
+
+    /// Passes a completion request and its raw response or error to the recorder if there is one
+    fn record(&self, json: &Value, response: Option<&str>, error: Option<&Error>) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record(
+                recorded_exchange(json, response.map(str::to_string), error),
+                self.keys.keys(),
+            );
+        }
+    }
+
+    /**
+    Sends a completion with streaming enabled and returns a stream of content deltas
+
+    The message is added to history as first element, the streamed response is not
+
+    Fails if the request failed
+    */
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            fields(
+                model = %model.name,
+                max_tokens = params.max_tokens,
+                temperature = params.temperature,
+                stream = params.stream
+            )
+        )
+    )]
+    pub async fn send_completion_stream(
+        &self,
+        model: &Model,
+        msg: Completion,
+        params: &CompletionParams,
+        history: &mut Option<VecDeque<Completion>>,
+    ) -> crate::Result<CompletionStream> {
+        add_history(history, msg.clone());
+
+        let messages = self.history_policy.apply(history_messages(&msg, history));
+        let json = completion_body(model, messages, params);
+        self.post_completion_stream(json, params.stream_usage).await
+    }
+
+    /**
+    Streams a completion for the provided messages as they are, without touching any history
+
+    Messages are expected in chronological order
+
+    Fails if the request failed
+    */
+    pub async fn send_messages_stream(
+        &self,
+        model: &Model,
+        messages: &[Completion],
+        params: &CompletionParams,
+    ) -> crate::Result<CompletionStream> {
+        let json = completion_body(model, messages.iter().collect(), params);
+        self.post_completion_stream(json, params.stream_usage).await
+    }
+
+    /**
+    Streams a completion for the provided messages on a spawned task and forwards it over a channel
+
+    Sends every content delta as `StreamEvent::Delta` followed by a single `StreamEvent::Done` with the
+    assembled reply, or a single `StreamEvent::Error` if the request or the stream failed. Meant for
+    UIs polling `try_recv` each frame instead of awaiting a stream, it must be called inside a tokio runtime
+
+    The channel holds up to `buffer` events, once it is full the task stops reading the response until
+    the receiver catches up, so a lagging receiver slows generation down instead of losing deltas.
+    Dropping the receiver stops the task and closes the connection
+    */
+    pub fn send_messages_channel(
+        &self,
+        model: &Model,
+        messages: &[Completion],
+        params: &CompletionParams,
+        buffer: usize,
+    ) -> mpsc::Receiver<StreamEvent> {
+        let (tx, rx) = mpsc::channel(buffer.max(1));
+        let json = completion_body(model, messages.iter().collect(), params);
+        let stream_usage = params.stream_usage;
+        let client = self.clone();
+        tokio::spawn(async move {
+            let event = match client.forward_stream(json, stream_usage, &tx).await {
+                Ok(Some(reply)) => StreamEvent::Done(reply),
+                Ok(None) => return,
+                Err(err) => StreamEvent::Error(err),
+            };
+            let _ = tx.send(event).await;
+        });
+        rx
+    }
+
+    /// Returns None once the receiver was dropped
+    async fn forward_stream(
+        &self,
+        json: Value,
+        stream_usage: bool,
+        tx: &mpsc::Sender<StreamEvent>,
+    ) -> crate::Result<Option<Completion>> {
+        let mut stream = self.post_completion_stream(json, stream_usage).await?;
+        while let Some(delta) = stream.next().await {
+            if tx.send(StreamEvent::Delta(delta?)).await.is_err() {
+                return Ok(None);
+            }
+        }
+        let res = stream.finish().await?;
+        Ok(res.choices.into_iter().next().map(|choice| choice.message))
+    }
+
+    /// `stream_usage` asks for usage, which arrives in a last chunk without choices
+    async fn post_completion_stream(
+        &self,
+        mut json: Value,
+        stream_usage: bool,
+    ) -> crate::Result<CompletionStream> {
+        if json.get("n").is_some() {
+            return Err(Error::InvalidInput(
+                "several choices can't be streamed".to_string(),
+            ));
+        }
+        json["stream"] = json!(true);
+        if stream_usage {
+            json["stream_options"] = json!({ "include_usage": true });
+        }
+        let req = self.request(Method::POST, "/chat/completions").json(&json);
+        let res = self.execute_stream(req).await;
+        if let Err(err) = &res {
+            self.record(&json, None, Some(err));
+        }
+        let stream = CompletionStream::new(res?, self.timeout, self.max_response_size);
+
+        // the reply is only known once the stream ended
+        let Some(recorder) = self.recorder.clone() else {
+            return Ok(stream);
+        };
+        let keys = self.keys.keys().to_vec();
+        Ok(stream.on_end(move |response, error| {
+            recorder.record(recorded_exchange(&json, response, error), &keys);
+        }))
+    }
+
+    /**
+    Retrieves all AIMLAPI models
+
+    Authorized with the client's api key unless it's empty and retried according to the retry policy
+
+    Will return an error if request fails
+    */
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", skip_all))]
+    pub async fn get_models(&self) -> crate::Result<Vec<ModelInfo>> {
+        let req = self.request(Method::GET, "/models");
+        let res = self.execute(req).await?;
+        let res = self.read_text("/models", res).await?;
+
+        let models: ModelsResponse = serde_json::from_str(&res)?;
+        Ok(models.into())
+    }
+
+    /**
+    Looks up a model by its exact, case sensitive id
+
+    Returns None if the API does not know the model, will return an error if request fails
+    */
+    pub async fn get_model(&self, name: &str) -> crate::Result<Option<ModelInfo>> {
+        Ok(model::find_model(self.get_models().await?, name))
+    }
+
+    /**
+    Retrieves the names of all AIMLAPI models
+
+    Will return an error if request fails
+    */
+    pub async fn model_names(&self) -> crate::Result<Vec<Model>> {
+        Ok(self
+            .get_models()
+            .await?
+            .iter()
+            .map(ModelInfo::model)
+            .collect())
+    }
+}
+
+#[derive(Serialize)]
+struct CompletionRequest<'a> {
+    model: &'a str,
+    messages: Vec<&'a Completion>,
+    #[serde(flatten)]
+    params: &'a CompletionParams,
+}
+
+/// Reports a request that failed for good
+fn failed(err: Error) -> Error {
+    #[cfg(feature = "tracing")]
+    tracing::warn!("request failed: {err}");
+    err
+}
+
+/// Streamed responses can't be parsed as a single response, fail before sending instead
+fn reject_stream(params: &CompletionParams) -> crate::Result<()> {
+    if params.stream {
+        return Err(Error::StreamingNotSupportedHere);
+    }
+    Ok(())
+}
+
+/// Passes successful responses through and turns any other status into an error
+async fn check_status(res: Response) -> crate::Result<Response> {
+    if res.status().is_success() {
+        return Ok(res);
+    }
+    Err(status_error(res).await)
+}
+
+/// Turns a non success response into an error including its body
+async fn status_error(res: Response) -> Error {
+    let status = res.status();
+    let body = res.text().await.unwrap_or_default();
+    Error::from_status(status, body)
+}
+
+/// Returns the messages to send in chronological order, `history` is expected to already contain `msg`
+fn history_messages<'a>(
+    msg: &'a Completion,
+    history: &'a Option<VecDeque<Completion>>,
+) -> Vec<&'a Completion> {
+    match history {
+        // history is stored newest first, the api expects chronological order
+        Some(history) => history.iter().rev().collect(),
+        None => vec![msg],
+    }
+}
+
+/// Completion exchange for the recorder, the timestamp is set when it's recorded
+fn recorded_exchange(
+    json: &Value,
+    response: Option<String>,
+    error: Option<&Error>,
+) -> RecordedExchange {
+    RecordedExchange {
+        timestamp: 0,
+        path: "/chat/completions".to_string(),
+        model: json["model"].as_str().map(str::to_string),
+        request: json.clone(),
+        response,
+        error: error.map(Error::to_string),
+    }
+}
+
+fn completion_body(model: &Model, messages: Vec<&Completion>, params: &CompletionParams) -> Value {
+    let mut json = json!(CompletionRequest {
+        model: &model.name,
+        messages,
+        params,
+    });
+    // sent as `stream_options` by the streaming methods only
+    json.as_object_mut().unwrap().remove("stream_usage");
+    if model.param_style() == ParamStyle::Reasoning {
+        let body = json.as_object_mut().unwrap();
+        if let Some(max_tokens) = body.remove("max_tokens") {
+            body.insert("max_completion_tokens".to_string(), max_tokens);
+        }
+        for unsupported in [
+            "temperature",
+            "top_p",
+            "frequency_penalty",
+            "presence_penalty",
+        ] {
+            body.remove(unsupported);
+        }
+    }
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proxies_are_not_added_to_a_custom_http_client() {
+        let proxy = || Proxy::all("http://proxy:8080").unwrap();
+        assert!(Client::new("key").with_proxy(proxy()).is_ok());
+
+        let res = Client::new("key")
+            .with_http_client(reqwest::Client::new())
+            .with_proxy(proxy());
+        assert!(matches!(res, Err(Error::InvalidInput(_))));
+    }
+
+    /// Tests sending through a `MockTransport`
+    #[cfg(feature = "test-util")]
+    mod sending {
+        use std::sync::Mutex;
+
+        use super::*;
+        use crate::{chat::CompletionRole, mock::MockTransport};
+
+        fn mock_client() -> (MockTransport, Client) {
+            let mock = MockTransport::new();
+            let client = Client::new("key")
+                .with_retry_policy(RetryPolicy::none())
+                .with_transport(mock.clone());
+            (mock, client)
+        }
+
+        /// Client that keeps every recorded exchange
+        fn recording_client() -> (MockTransport, Client, Arc<Mutex<Vec<RecordedExchange>>>) {
+            let (mock, client) = mock_client();
+            let exchanges = Arc::new(Mutex::new(Vec::new()));
+            let sink = exchanges.clone();
+            let client = client.with_recorder(RequestRecorder::from_fn(move |exchange| {
+                sink.lock().unwrap().push(exchange.clone());
+            }));
+            (mock, client, exchanges)
+        }
+
+        fn model() -> Model {
+            Model::from("gpt-4o".to_string())
+        }
+
+        fn reply(content: &str) -> Value {
+            json!({ "choices": [{ "message": { "role": "assistant", "content": content } }] })
+        }
+
+        #[tokio::test]
+        async fn history_is_sent_in_chronological_order_with_replies() {
+            let (mock, client) = mock_client();
+            mock.push_json(200, reply("hi, how can I help?"));
+            mock.push_json(200, reply("sure"));
+            let params = CompletionParams::default();
+            let mut history = Some(VecDeque::new());
+
+            client
+                .send_completion(&model(), Completion::user("hello"), &params, &mut history)
+                .await
+                .unwrap();
+            client
+                .send_completion(
+                    &model(),
+                    Completion::user("tell me more"),
+                    &params,
+                    &mut history,
+                )
+                .await
+                .unwrap();
+
+            let body = mock.requests()[1].json().unwrap();
+            assert_eq!(
+                body["messages"],
+                json!([
+                    { "role": "user", "content": "hello" },
+                    { "role": "assistant", "content": "hi, how can I help?" },
+                    { "role": "user", "content": "tell me more" },
+                ])
+            );
+            let history = history.unwrap();
+            assert_eq!(history.len(), 4);
+            assert_eq!(history[0].get_role(), CompletionRole::ASSISTANT);
+        }
+
+        #[tokio::test]
+        async fn key_with_newline_is_rejected() {
+            let mock = MockTransport::new();
+            let client = Client::new("abc\ndef").with_transport(mock.clone());
+            let messages = [Completion::user("hello")];
+
+            let err = client
+                .send_messages(&model(), &messages, &CompletionParams::default())
+                .await
+                .unwrap_err();
+
+            assert!(matches!(err, Error::InvalidApiKey));
+            assert!(mock.requests().is_empty());
+        }
+
+        #[tokio::test]
+        async fn surrounding_whitespace_is_trimmed_from_keys() {
+            let mock = MockTransport::new();
+            mock.push_json(200, reply("hi"));
+            let client = Client::new(" key\n").with_transport(mock.clone());
+            let messages = [Completion::user("hello")];
+
+            client
+                .send_messages(&model(), &messages, &CompletionParams::default())
+                .await
+                .unwrap();
+
+            let request = &mock.requests()[0];
+            assert_eq!(request.headers[AUTHORIZATION], "Bearer key");
+        }
+
+        #[tokio::test]
+        async fn empty_key_pool_is_rejected() {
+            let mock = MockTransport::new();
+            let client = Client::new("key")
+                .with_keys(Vec::new())
+                .with_transport(mock.clone());
+            let messages = [Completion::user("hello")];
+
+            let err = client
+                .send_messages(&model(), &messages, &CompletionParams::default())
+                .await
+                .unwrap_err();
+
+            assert!(matches!(err, Error::InvalidApiKey));
+            assert!(mock.requests().is_empty());
+        }
+
+        #[tokio::test]
+        async fn streams_ask_for_usage_when_requested() {
+            let (mock, client) = mock_client();
+            mock.push_response(
+                200,
+                concat!(
+                    "data: {\"choices\":[{\"delta\":{\"content\":\"hi\"},\"finish_reason\":\"stop\"}]}\n\n",
+                    "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":5,\"completion_tokens\":1,",
+                    "\"total_tokens\":6}}\n\n",
+                    "data: [DONE]\n\n",
+                ),
+            );
+            let messages = [Completion::user("hello")];
+            let params = CompletionParams::builder()
+                .stream_usage(true)
+                .build()
+                .unwrap();
+            let stream = client
+                .send_messages_stream(&model(), &messages, &params)
+                .await
+                .unwrap();
+            let res = stream.finish().await.unwrap();
+
+            let body = mock.requests()[0].json().unwrap();
+            assert_eq!(body["stream"], true);
+            assert_eq!(body["stream_options"]["include_usage"], true);
+            assert_eq!(res.usage.unwrap().total_tokens, 6);
+        }
+
+        #[tokio::test]
+        async fn streams_leave_usage_out_by_default() {
+            let (mock, client) = mock_client();
+            mock.push_response(
+                200,
+                "data: {\"choices\":[{\"delta\":{\"content\":\"hi\"}}]}\n\ndata: [DONE]\n\n",
+            );
+            let messages = [Completion::user("hello")];
+            let stream = client
+                .send_messages_stream(&model(), &messages, &CompletionParams::default())
+                .await
+                .unwrap();
+            let res = stream.finish().await.unwrap();
+
+            let body = mock.requests()[0].json().unwrap();
+            assert!(body.get("stream_options").is_none());
+            assert!(body.get("stream_usage").is_none());
+            assert!(res.usage.is_none());
+        }
+
+        #[tokio::test]
+        async fn streams_are_recorded_with_the_assembled_reply() {
+            let (mock, client, exchanges) = recording_client();
+            mock.push_response(
+                200,
+                concat!(
+                    "data: {\"choices\":[{\"delta\":{\"content\":\"Let me \"}}]}\n\n",
+                    "data: {\"choices\":[{\"delta\":{\"content\":\"check\",\"tool_calls\":[{\"index\":0,",
+                    "\"id\":\"call_1\",\"function\":{\"name\":\"get_weather\",\"arguments\":\"{}\"}}]}}]}\n\n",
+                    "data: [DONE]\n\n",
+                ),
+            );
+            let messages = [Completion::user("weather?")];
+            let stream = client
+                .send_messages_stream(&model(), &messages, &CompletionParams::default())
+                .await
+                .unwrap();
+            assert!(exchanges.lock().unwrap().is_empty());
+            stream.finish().await.unwrap();
+
+            let exchanges = exchanges.lock().unwrap();
+            assert_eq!(exchanges.len(), 1);
+            assert!(exchanges[0].error.is_none());
+            let response: CompletionResponse =
+                serde_json::from_str(exchanges[0].response.as_deref().unwrap()).unwrap();
+            let message = &response.choices[0].message;
+            assert_eq!(message.content.text(), "Let me check");
+            assert_eq!(message.tool_calls.as_ref().unwrap()[0].name, "get_weather");
+        }
+
+        #[tokio::test]
+        async fn dropped_streams_are_recorded_as_cancelled() {
+            let (mock, client, exchanges) = recording_client();
+            mock.push_response(
+                200,
+                "data: {\"choices\":[{\"delta\":{\"content\":\"partial\"}}]}\n\n",
+            );
+            let messages = [Completion::user("hello")];
+            let mut stream = client
+                .send_messages_stream(&model(), &messages, &CompletionParams::default())
+                .await
+                .unwrap();
+            assert_eq!(stream.next().await.unwrap().unwrap(), "partial");
+            drop(stream);
+
+            let exchanges = exchanges.lock().unwrap();
+            assert_eq!(exchanges.len(), 1);
+            assert_eq!(
+                exchanges[0].error.as_deref(),
+                Some(Error::Cancelled.to_string().as_str())
+            );
+            assert!(exchanges[0]
+                .response
+                .as_deref()
+                .unwrap()
+                .contains("partial"));
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::fmt;
+
+use reqwest::StatusCode;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The API responded with a non success status, `body` holds the response text
+    Http { status: StatusCode, body: String },
+    /**
+    The API responded with a non success status and an error body like `{ "error": { "message", "code" } }`
+
+    Takes precedence over `Unauthorized`, `RateLimited` and `Http`, use `status` to check the status
+    */
+    Api {
+        status: StatusCode,
+        message: String,
+        code: Option<String>,
+    },
+    /// The API rejected the api key without explaining why
+    Unauthorized,
+    /// The api key contains characters that can't be sent in a header, like newlines, or no key was provided
+    InvalidApiKey,
+    /// The API is rate limiting requests without explaining why
+    RateLimited,
+    /// The response body (or a saved file) could not be (de)serialized
+    Deserialize(serde_json::Error),
+    /// The response did not contain any choices
+    EmptyChoices,
+    /// The reply's content is empty or only whitespace and it has no tool calls either
+    EmptyContent,
+    /// The response did not have the expected shape
+    InvalidResponse(String),
+    /// The API reported an error in the middle of a stream
+    Stream(String),
+    /// An argument was rejected before sending the request
+    InvalidInput(String),
+    /// Moderation flagged the message so it was not sent, holds the flagged categories
+    Flagged { categories: Vec<String> },
+    /// The chat specified by the provided uuid does not exist
+    ChatNotFound,
+    /// The response body or streamed content exceeded the client's max response size
+    ResponseTooLarge { limit: usize },
+    /// `stream` is enabled in the params passed to a method that does not stream
+    StreamingNotSupportedHere,
+    /// The request could not be sent or the response could not be read
+    Network(reqwest::Error),
+    /// The request or stream took longer than the client's timeout, or a generation didn't finish in time
+    Timeout,
+    /// The request or stream was cancelled through a cancellation token
+    Cancelled,
+    /// Reading or writing a file failed
+    Io(std::io::Error),
+    /// The request still failed after being retried, `attempts` includes the first try
+    Retried { attempts: u32, source: Box<Error> },
+}
+
+impl Error {
+    /// Maps a non success status and its response body to the matching error
+    pub(crate) fn from_status(status: StatusCode, body: String) -> Self {
+        if let Some((message, code)) = parse_error_body(&body) {
+            return Self::Api {
+                status,
+                message,
+                code,
+            };
+        }
+        match status {
+            StatusCode::UNAUTHORIZED => Self::Unauthorized,
+            StatusCode::TOO_MANY_REQUESTS => Self::RateLimited,
+            status => Self::Http { status, body },
+        }
+    }
+
+    /// Wraps an error with the number of attempts made if the request was retried
+    pub(crate) fn with_attempts(err: Error, attempts: u32) -> Self {
+        match attempts {
+            0 | 1 => err,
+            attempts => Self::Retried {
+                attempts,
+                source: Box::new(err),
+            },
+        }
+    }
+
+    /// Returns the underlying error, unwrapping `Retried`
+    pub fn root(&self) -> &Error {
+        match self {
+            Self::Retried { source, .. } => source.root(),
+            err => err,
+        }
+    }
+
+    /// Returns the status the API responded with, None if the error did not come from a response
+    pub fn status(&self) -> Option<StatusCode> {
+        match self.root() {
+            Self::Http { status, .. } | Self::Api { status, .. } => Some(*status),
+            Self::Unauthorized => Some(StatusCode::UNAUTHORIZED),
+            Self::RateLimited => Some(StatusCode::TOO_MANY_REQUESTS),
+            _ => None,
+        }
+    }
+}
+
+/// Extracts the message and code of an error body, accepting `{ "error": "message" }` as well
+fn parse_error_body(body: &str) -> Option<(String, Option<String>)> {
+    let body: serde_json::Value = serde_json::from_str(body).ok()?;
+    let error = body.get("error")?;
+    if let Some(message) = error.as_str() {
+        return Some((message.to_string(), None));
+    }
+    let message = error.get("message")?.as_str()?.to_string();
+    // codes are strings like "invalid_api_key" for most providers but numbers for some
+    let code = match error.get("code") {
+        Some(serde_json::Value::String(code)) => Some(code.clone()),
+        Some(serde_json::Value::Number(code)) => Some(code.to_string()),
+        _ => None,
+    };
+    Some((message, code))
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Http { status, body } if body.is_empty() => write!(f, "request failed {status}"),
+            Self::Http { status, body } => write!(f, "request failed {status}: {body}"),
+            Self::Api {
+                status,
+                message,
+                code: Some(code),
+            } => write!(f, "request failed {status}: {message} ({code})"),
+            Self::Api { status, message, .. } => write!(f, "request failed {status}: {message}"),
+            Self::Unauthorized => write!(f, "api key was rejected"),
+            Self::InvalidApiKey => write!(f, "api key is missing or contains invalid characters"),
+            Self::RateLimited => write!(f, "rate limited"),
+            Self::Deserialize(err) => write!(f, "failed to deserialize response: {err}"),
+            Self::EmptyChoices => write!(f, "response contains no choices"),
+            Self::EmptyContent => write!(f, "reply has no content and no tool calls"),
+            Self::InvalidResponse(msg) => write!(f, "invalid response: {msg}"),
+            Self::Stream(msg) => write!(f, "stream failed: {msg}"),
+            Self::InvalidInput(msg) => write!(f, "invalid input: {msg}"),
+            Self::Flagged { categories } if categories.is_empty() => {
+                write!(f, "message was flagged by moderation")
+            }
+            Self::Flagged { categories } => {
+                write!(
+                    f,
+                    "message was flagged by moderation: {}",
+                    categories.join(", ")
+                )
+            }
+            Self::ChatNotFound => write!(f, "chat does not exist"),
+            Self::ResponseTooLarge { limit } => {
+                write!(f, "response exceeded the limit of {limit} bytes")
+            }
+            Self::StreamingNotSupportedHere => write!(
+                f,
+                "params have stream enabled, use a streaming method like send_completion_stream or disable stream"
+            ),
+            Self::Network(err) => write!(f, "network error: {err}"),
+            Self::Timeout => write!(f, "request timed out"),
+            Self::Cancelled => write!(f, "request was cancelled"),
+            Self::Io(err) => write!(f, "io error: {err}"),
+            Self::Retried { attempts, source } => {
+                write!(f, "{source} (after {attempts} attempts)")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Deserialize(err) => Some(err),
+            Self::Network(err) => Some(err),
+            Self::Io(err) => Some(err),
+            Self::Retried { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Deserialize(err)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            return Self::Timeout;
+        }
+        Self::Network(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
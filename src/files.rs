@@ -0,0 +1,142 @@
+use std::fmt;
+
+use reqwest::{
+    multipart::{Form, Part},
+    Method,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{client::Client, Error};
+
+/**
+Uploads a file for use in later requests, e.g. with `purpose` set to `assistants`
+
+Fails if the file is empty or the request failed
+*/
+pub async fn upload_file(
+    api_key: &str,
+    bytes: Vec<u8>,
+    filename: &str,
+    purpose: &str,
+) -> crate::Result<FileId> {
+    Ok(Client::new(api_key)
+        .upload_file(bytes, filename, purpose)
+        .await?
+        .id)
+}
+
+/**
+Lists every uploaded file
+
+Will return an error if request fails
+*/
+pub async fn list_files(api_key: &str) -> crate::Result<Vec<FileObject>> {
+    Client::new(api_key).list_files().await
+}
+
+/**
+Deletes an uploaded file
+
+Returns if the API reported the file as deleted, will return an error if request fails
+*/
+pub async fn delete_file(api_key: &str, id: &FileId) -> crate::Result<bool> {
+    Client::new(api_key).delete_file(id).await
+}
+
+impl Client {
+    /**
+    Uploads a file for use in later requests, e.g. with `purpose` set to `assistants`
+
+    Fails if the file is empty or the request failed
+    */
+    pub async fn upload_file(
+        &self,
+        bytes: Vec<u8>,
+        filename: &str,
+        purpose: &str,
+    ) -> crate::Result<FileObject> {
+        if bytes.is_empty() {
+            return Err(Error::InvalidInput("file is empty".to_string()));
+        }
+
+        let file = Part::bytes(bytes).file_name(filename.to_string());
+        let form = Form::new()
+            .text("purpose", purpose.to_string())
+            .part("file", file);
+        let req = self.request(Method::POST, "/files").multipart(form);
+        let res = self.execute(req).await?;
+        let res = self.read_text("/files", res).await?;
+        Ok(serde_json::from_str(&res)?)
+    }
+
+    /**
+    Lists every uploaded file
+
+    Will return an error if request fails
+    */
+    pub async fn list_files(&self) -> crate::Result<Vec<FileObject>> {
+        let req = self.request(Method::GET, "/files");
+        let res = self.execute(req).await?;
+        let res = self.read_text("/files", res).await?;
+        let res: FilesResponse = serde_json::from_str(&res)?;
+        Ok(res.data)
+    }
+
+    /**
+    Deletes an uploaded file
+
+    Returns if the API reported the file as deleted, will return an error if request fails
+    */
+    pub async fn delete_file(&self, id: &FileId) -> crate::Result<bool> {
+        let path = format!("/files/{id}");
+        let req = self.request(Method::DELETE, &path);
+        let res = self.execute(req).await?;
+        let res = self.read_text(&path, res).await?;
+        let res: DeleteResponse = serde_json::from_str(&res)?;
+        Ok(res.deleted)
+    }
+}
+
+/// Id of an uploaded file, attach it to a message with `Completion::with_file`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(transparent)]
+pub struct FileId(pub String);
+
+impl fmt::Display for FileId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<String> for FileId {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+/// Uploaded file as returned by `/files`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct FileObject {
+    pub id: FileId,
+    #[serde(default)]
+    pub filename: Option<String>,
+    #[serde(default)]
+    pub purpose: Option<String>,
+    /// Size in bytes
+    #[serde(default)]
+    pub bytes: Option<u64>,
+    /// Seconds since the unix epoch the file was uploaded at
+    #[serde(default)]
+    pub created_at: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct FilesResponse {
+    data: Vec<FileObject>,
+}
+
+#[derive(Deserialize)]
+struct DeleteResponse {
+    #[serde(default)]
+    deleted: bool,
+}
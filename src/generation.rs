@@ -0,0 +1,291 @@
+/*!
+Asynchronous generation jobs shared by the video and audio models
+
+Starting a generation returns a `GenerationId` right away, the result is fetched by polling
+until the status is `GenerationStatus::Completed` or `GenerationStatus::Failed`,
+`list_generations` finds earlier jobs again whose ids were lost
+*/
+
+use std::{fmt, time::Duration};
+
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::time::Instant;
+
+use crate::{client::Client, Error};
+
+const GENERATIONS_PATH: &str = "/v2/generations";
+
+/**
+Lists earlier generation jobs matching the filter, e.g. to resume polling after a restart
+
+Will return an error if request fails
+*/
+pub async fn list_generations(
+    api_key: &str,
+    filter: &GenerationFilter,
+) -> crate::Result<Vec<GenerationSummary>> {
+    Client::new(api_key).list_generations(filter).await
+}
+
+/// Id of a generation job, persist it to resume polling later
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(transparent)]
+pub struct GenerationId(pub String);
+
+impl fmt::Display for GenerationId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<String> for GenerationId {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+/**
+State of a generation job
+
+Providers name their states differently, e.g. `generating` and `in_progress` both map to `Processing`,
+jobs that ended without a result like `cancelled` or `expired` ones map to `Failed`
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(from = "String", into = "String")]
+pub enum GenerationStatus {
+    Queued,
+    Processing,
+    Completed,
+    Failed,
+    /// States this crate doesn't know yet, holds the raw value
+    Other(String),
+}
+
+impl GenerationStatus {
+    /// Returns if the job won't change anymore
+    pub fn is_finished(&self) -> bool {
+        matches!(self, Self::Completed | Self::Failed)
+    }
+}
+
+impl From<String> for GenerationStatus {
+    fn from(value: String) -> Self {
+        match value.to_lowercase().as_str() {
+            "queued" | "queue" | "pending" | "waiting" => Self::Queued,
+            "processing" | "generating" | "in_progress" | "active" | "dreaming" => Self::Processing,
+            "completed" | "complete" | "succeeded" | "success" => Self::Completed,
+            "failed" | "error" | "cancelled" | "canceled" | "expired" | "timeout" | "timed_out"
+            | "rejected" => Self::Failed,
+            _ => Self::Other(value),
+        }
+    }
+}
+
+impl From<GenerationStatus> for String {
+    fn from(value: GenerationStatus) -> Self {
+        match value {
+            GenerationStatus::Queued => "queued".to_string(),
+            GenerationStatus::Processing => "processing".to_string(),
+            GenerationStatus::Completed => "completed".to_string(),
+            GenerationStatus::Failed => "failed".to_string(),
+            GenerationStatus::Other(status) => status,
+        }
+    }
+}
+
+/// Current state of a generation job
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Generation {
+    pub id: GenerationId,
+    pub status: GenerationStatus,
+    /// Url of the generated file, None until the job completed
+    pub url: Option<String>,
+    /// Why the job failed, None unless the status is `Failed`
+    pub error: Option<String>,
+    /// The whole response, for metadata like duration or the seed that was used
+    pub raw: Value,
+}
+
+impl Generation {
+    /// Reads a job from the differently shaped responses of the generation endpoints
+    pub(crate) fn from_value(raw: Value) -> crate::Result<Self> {
+        let id = raw["id"]
+            .as_str()
+            .or_else(|| raw["generation_id"].as_str())
+            .ok_or_else(|| Error::InvalidResponse("generation has no id".to_string()))?;
+        let status = raw["status"].as_str().unwrap_or("queued");
+        let url = ["video", "audio_file", "audio"]
+            .iter()
+            .find_map(|field| raw[field]["url"].as_str())
+            .or_else(|| raw["url"].as_str());
+        let error = raw["error"]
+            .as_str()
+            .or_else(|| raw["error"]["message"].as_str());
+        Ok(Self {
+            id: GenerationId(id.to_string()),
+            status: GenerationStatus::from(status.to_string()),
+            url: url.map(str::to_string),
+            error: error.map(str::to_string),
+            raw,
+        })
+    }
+}
+
+/// Kind of output a generation job produces
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum GenerationKind {
+    Image,
+    Video,
+    Audio,
+    /// Kinds this crate doesn't know yet
+    #[serde(other)]
+    Other,
+}
+
+impl GenerationKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Image => "image",
+            Self::Video => "video",
+            Self::Audio => "audio",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// Narrows down `list_generations`, unset fields match every job
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GenerationFilter {
+    pub kind: Option<GenerationKind>,
+    pub status: Option<GenerationStatus>,
+}
+
+/// Generation job as returned by `list_generations`, fetch it for the full result
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GenerationSummary {
+    #[serde(alias = "generation_id")]
+    pub id: GenerationId,
+    #[serde(default, rename = "type")]
+    pub kind: Option<GenerationKind>,
+    #[serde(default = "queued")]
+    pub status: GenerationStatus,
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Seconds since the unix epoch the job was started at
+    #[serde(default)]
+    pub created_at: Option<u64>,
+}
+
+fn queued() -> GenerationStatus {
+    GenerationStatus::Queued
+}
+
+/// Shapes the generations endpoint may respond with
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GenerationsResponse {
+    Wrapped { data: Vec<GenerationSummary> },
+    List(Vec<GenerationSummary>),
+}
+
+impl Client {
+    /**
+    Lists earlier generation jobs matching the filter, e.g. to resume polling after a restart
+
+    The filter is sent along and applied to the response as well, in case the API ignores it
+
+    Will return an error if request fails
+    */
+    pub async fn list_generations(
+        &self,
+        filter: &GenerationFilter,
+    ) -> crate::Result<Vec<GenerationSummary>> {
+        let mut query = Vec::new();
+        if let Some(kind) = filter.kind {
+            query.push(("type", kind.as_str().to_string()));
+        }
+        if let Some(status) = &filter.status {
+            query.push(("status", String::from(status.clone())));
+        }
+        let req = self.request(Method::GET, GENERATIONS_PATH).query(&query);
+        let res = self.execute(req).await?;
+        let res = self.read_text(GENERATIONS_PATH, res).await?;
+        let generations = match serde_json::from_str(&res)? {
+            GenerationsResponse::Wrapped { data } => data,
+            GenerationsResponse::List(generations) => generations,
+        };
+        Ok(generations
+            .into_iter()
+            .filter(|generation| {
+                filter.kind.is_none_or(|kind| generation.kind == Some(kind))
+                    && filter
+                        .status
+                        .as_ref()
+                        .is_none_or(|status| generation.status == *status)
+            })
+            .collect())
+    }
+
+    /// Starts a job at the generation endpoint `path` and returns its id
+    pub(crate) async fn start_generation(
+        &self,
+        path: &str,
+        body: &Value,
+    ) -> crate::Result<GenerationId> {
+        Ok(self.post_generation(path, body).await?.id)
+    }
+
+    /// Same as `start_generation` but returns the whole job, for models answering right away
+    pub(crate) async fn post_generation(
+        &self,
+        path: &str,
+        body: &Value,
+    ) -> crate::Result<Generation> {
+        let req = self.request(Method::POST, path).json(body);
+        let res = self.execute(req).await?;
+        let res = self.read_text(path, res).await?;
+        Generation::from_value(serde_json::from_str(&res)?)
+    }
+
+    /// Retrieves the current state of a job from the generation endpoint `path`
+    pub(crate) async fn fetch_generation_at(
+        &self,
+        path: &str,
+        id: &GenerationId,
+    ) -> crate::Result<Generation> {
+        let req = self
+            .request(Method::GET, path)
+            .query(&[("generation_id", &id.0)]);
+        let res = self.execute(req).await?;
+        let res = self.read_text(path, res).await?;
+        Generation::from_value(serde_json::from_str(&res)?)
+    }
+
+    /**
+    Polls a job every `poll_interval` until it completed or failed
+
+    Fails with `Error::Timeout` once it's still unfinished after `max_wait`
+    */
+    pub(crate) async fn wait_for_generation_at(
+        &self,
+        path: &str,
+        id: &GenerationId,
+        poll_interval: Duration,
+        max_wait: Duration,
+    ) -> crate::Result<Generation> {
+        let deadline = Instant::now() + max_wait;
+        loop {
+            let generation = self.fetch_generation_at(path, id).await?;
+            if generation.status.is_finished() {
+                return Ok(generation);
+            }
+            if Instant::now() + poll_interval > deadline {
+                return Err(Error::Timeout);
+            }
+            tokio::time::sleep(poll_interval).await;
+        }
+    }
+}
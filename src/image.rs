@@ -0,0 +1,131 @@
+use reqwest::{
+    multipart::{Form, Part},
+    Method,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{client::Client, model::Model, Error};
+
+/**
+Edits an image according to the prompt, only the transparent areas of `mask` are changed
+
+Fails if the image or mask is not a PNG or the request failed
+*/
+pub async fn edit_image(
+    api_key: &str,
+    model: &Model,
+    image: Vec<u8>,
+    mask: Option<Vec<u8>>,
+    prompt: &str,
+    params: &ImageParams,
+) -> crate::Result<Vec<GeneratedImage>> {
+    Client::new(api_key)
+        .edit_image(model, image, mask, prompt, params)
+        .await
+}
+
+impl Client {
+    /**
+    Edits an image according to the prompt, only the transparent areas of `mask` are changed
+
+    Without a mask the model decides what to change, usually relying on transparency in the image itself
+
+    Fails if the image or mask is not a PNG or the request failed
+    */
+    pub async fn edit_image(
+        &self,
+        model: &Model,
+        image: Vec<u8>,
+        mask: Option<Vec<u8>>,
+        prompt: &str,
+        params: &ImageParams,
+    ) -> crate::Result<Vec<GeneratedImage>> {
+        check_png("image", &image)?;
+        if let Some(mask) = &mask {
+            check_png("mask", mask)?;
+        }
+
+        let mut form = Form::new()
+            .text("model", model.name.clone())
+            .text("prompt", prompt.to_string())
+            .part("image", png_part(image, "image.png")?);
+        if let Some(mask) = mask {
+            form = form.part("mask", png_part(mask, "mask.png")?);
+        }
+        if let Some(n) = params.n {
+            form = form.text("n", n.to_string());
+        }
+        if let Some(size) = &params.size {
+            form = form.text("size", size.clone());
+        }
+        if let Some(format) = params.response_format {
+            form = form.text("response_format", format.as_str());
+        }
+
+        let req = self.request(Method::POST, "/images/edits").multipart(form);
+        let res = self.execute(req).await?;
+        let res = self.read_text("/images/edits", res).await?;
+        let res: ImagesResponse = serde_json::from_str(&res)?;
+        Ok(res.data)
+    }
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+fn check_png(name: &str, bytes: &[u8]) -> crate::Result<()> {
+    if !bytes.starts_with(PNG_SIGNATURE) {
+        return Err(Error::InvalidInput(format!("{name} is not a png")));
+    }
+    Ok(())
+}
+
+fn png_part(bytes: Vec<u8>, filename: &str) -> crate::Result<Part> {
+    Ok(Part::bytes(bytes)
+        .file_name(filename.to_string())
+        .mime_str("image/png")?)
+}
+
+/// Optional parameters for image requests, unset ones are left to the API's defaults
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct ImageParams {
+    /// How many images to generate
+    pub n: Option<u32>,
+    /// Size like `1024x1024`, supported sizes depend on the model
+    pub size: Option<String>,
+    pub response_format: Option<ImageResponseFormat>,
+}
+
+/// Whether images are returned as urls or inline as base64
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageResponseFormat {
+    #[default]
+    Url,
+    B64Json,
+}
+
+impl ImageResponseFormat {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Url => "url",
+            Self::B64Json => "b64_json",
+        }
+    }
+}
+
+/// Generated image, either `url` or `b64_json` is set depending on the requested format
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GeneratedImage {
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub b64_json: Option<String>,
+    /// Prompt the model actually used, some models rewrite the prompt
+    #[serde(default)]
+    pub revised_prompt: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ImagesResponse {
+    data: Vec<GeneratedImage>,
+}
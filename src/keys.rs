@@ -0,0 +1,103 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use reqwest::StatusCode;
+
+/// How long a key that hit its quota is skipped by default
+pub(crate) const DEFAULT_COOLDOWN: Duration = Duration::from_secs(60);
+
+/**
+Pool of api keys shared by clones of a client
+
+Keys are handed out round-robin, a key that hit its quota is benched for the cooldown period
+and skipped until it expires
+*/
+#[derive(Debug)]
+pub(crate) struct KeyPool {
+    keys: Vec<String>,
+    cooldown: Duration,
+    state: Mutex<PoolState>,
+}
+
+#[derive(Debug)]
+struct PoolState {
+    next: usize,
+    benched_until: Vec<Option<Instant>>,
+}
+
+impl KeyPool {
+    /// Surrounding whitespace is trimmed from every key, e.g. a trailing newline when pasted
+    pub(crate) fn new(keys: Vec<String>, cooldown: Duration) -> Self {
+        let keys: Vec<String> = keys.into_iter().map(|key| key.trim().to_string()).collect();
+        let benched_until = vec![None; keys.len()];
+        Self {
+            keys,
+            cooldown,
+            state: Mutex::new(PoolState {
+                next: 0,
+                benched_until,
+            }),
+        }
+    }
+
+    pub(crate) fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    pub(crate) fn cooldown(&self) -> Duration {
+        self.cooldown
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub(crate) fn key(&self, index: usize) -> &str {
+        &self.keys[index]
+    }
+
+    /**
+    Returns the index of the key to use for the next request
+
+    If every key is benched the one whose cooldown expires first is used, None if the pool is empty
+    */
+    pub(crate) fn pick(&self) -> Option<usize> {
+        if self.keys.is_empty() {
+            return None;
+        }
+
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        let len = self.keys.len();
+        let available = (0..len)
+            .map(|offset| (state.next + offset) % len)
+            .find(|&index| state.benched_until[index].is_none_or(|until| until <= now));
+        let index = available.unwrap_or_else(|| {
+            (0..len)
+                .min_by_key(|&index| state.benched_until[index])
+                .unwrap_or(0)
+        });
+        state.benched_until[index] = state.benched_until[index].filter(|&until| until > now);
+        state.next = (index + 1) % len;
+        Some(index)
+    }
+
+    /// Benches a key for the cooldown period, returns if another key is available right now
+    pub(crate) fn bench(&self, index: usize) -> bool {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+        state.benched_until[index] = Some(now + self.cooldown);
+        state
+            .benched_until
+            .iter()
+            .enumerate()
+            .any(|(other, until)| other != index && until.is_none_or(|until| until <= now))
+    }
+}
+
+/// Returns if a status means the key ran into its rate limit or quota
+pub(crate) fn is_quota_error(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::PAYMENT_REQUIRED
+}
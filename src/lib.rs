@@ -1,8 +1,36 @@
+pub mod assistants;
 pub mod chat;
+pub mod client;
+pub mod error;
+pub mod generation;
+mod keys;
 pub mod model;
+pub mod moderation;
+pub mod prelude;
+pub mod provider;
+pub mod recorder;
+pub mod retry;
+pub mod stream;
+pub mod tokens;
+pub mod tools;
+pub mod transport;
+pub mod video;
 
+#[cfg(feature = "audio")]
+pub mod audio;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "files")]
+pub mod files;
+#[cfg(feature = "image")]
+pub mod image;
 #[cfg(feature = "managers")]
 pub mod managers;
+#[cfg(feature = "test-util")]
+pub mod mock;
+
+pub use chat::ask;
+pub use error::{Error, Result};
 
 pub const BASE_API_URL: &str = "https://api.aimlapi.com";
 
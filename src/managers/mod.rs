@@ -1,12 +1,23 @@
-use std::collections::{HashMap, VecDeque};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs, io,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-use anyhow::anyhow;
+use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::{
-    chat::{add_history, send_completion, Completion, CompletionParams, CompletionRole},
+    chat::{
+        add_history, Completion, CompletionParams, CompletionResponse, CompletionRole, Content,
+        FinishReason, HistoryPolicy, Usage,
+    },
+    client::Client,
     model::Model,
+    tokens::count_tokens,
+    Error,
 };
 
 #[derive(Serialize, Deserialize, Debug, Default, Clone)]
@@ -23,6 +34,35 @@ impl ChatManager {
         }
     }
 
+    /// Saves the manager including the current chat as JSON to the provided path
+    pub fn save_to_path(&self, path: impl AsRef<Path>) -> crate::Result<()> {
+        let json = serde_json::to_string(self)?;
+        fs::write(path, json)?;
+        Ok(())
+    }
+
+    /**
+    Loads a manager previously saved with `save_to_path`
+
+    Fails if the file does not exist or is not a valid manager
+    */
+    pub fn load_from_path(path: impl AsRef<Path>) -> crate::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    /**
+    Loads a manager previously saved with `save_to_path`
+
+    Returns a new manager if the file does not exist
+    */
+    pub fn load_or_default(path: impl AsRef<Path>) -> crate::Result<Self> {
+        match Self::load_from_path(path) {
+            Err(Error::Io(err)) if err.kind() == io::ErrorKind::NotFound => Ok(Self::new()),
+            res => res,
+        }
+    }
+
     /// Returns if chat specified by provided uuid exists
     pub fn chat_exists(&self, chat_uuid: Uuid) -> bool {
         self.chats.contains_key(&chat_uuid)
@@ -43,14 +83,58 @@ impl ChatManager {
         chat_uuid
     }
 
+    /**
+    Copies a chat including its history into a new chat, both continue independently
+
+    Returns the new chat's uuid
+
+    Fails if chat does not exist
+    */
+    pub fn fork_chat(&mut self, chat_uuid: Uuid) -> crate::Result<Uuid> {
+        let mut fork = self
+            .chats
+            .get(&chat_uuid)
+            .ok_or(Error::ChatNotFound)?
+            .clone();
+        fork.created_at = unix_now();
+        let fork_uuid = Uuid::new_v4();
+        self.chats.insert(fork_uuid, fork);
+        Ok(fork_uuid)
+    }
+
+    /**
+    Same as `fork_chat` but the fork only keeps history up to and including `message_index`
+
+    `message_index` counts in chronological order, 0 is the oldest message in history
+
+    Fails if chat does not exist or the index is out of bounds
+    */
+    pub fn fork_chat_at(&mut self, chat_uuid: Uuid, message_index: usize) -> crate::Result<Uuid> {
+        let chat = self.chats.get(&chat_uuid).ok_or(Error::ChatNotFound)?;
+        let len = chat.history.as_ref().map_or(0, VecDeque::len);
+        if message_index >= len {
+            return Err(Error::InvalidInput(format!(
+                "no message at index {message_index}"
+            )));
+        }
+
+        let fork_uuid = self.fork_chat(chat_uuid)?;
+        let fork = self.chats.get_mut(&fork_uuid).unwrap();
+        // history is stored newest first, drop everything newer than the index from the front
+        if let Some(history) = fork.history.as_mut() {
+            history.drain(..len - message_index - 1);
+        }
+        Ok(fork_uuid)
+    }
+
     /**
     Removes a chat by uuid and sets current chat to nil if it's uuid is equal to the provided one
 
     Fails if chat does not exist
     */
-    pub fn remove_chat(&mut self, chat_uuid: Uuid) -> anyhow::Result<()> {
+    pub fn remove_chat(&mut self, chat_uuid: Uuid) -> crate::Result<()> {
         if !self.chat_exists(chat_uuid) {
-            return Err(anyhow!("chat does not exist"));
+            return Err(Error::ChatNotFound);
         }
         self.chats.remove(&chat_uuid);
         if self.current_chat == chat_uuid {
@@ -59,6 +143,69 @@ impl ChatManager {
         Ok(())
     }
 
+    /**
+    Lists every chat's uuid, title and model, oldest chat first
+
+    Meant for rendering a chat list, use `get_chat` for everything else
+    */
+    pub fn list_chats(&self) -> Vec<(Uuid, Option<String>, Model)> {
+        self.sorted_chats()
+            .map(|(uuid, chat)| (*uuid, chat.title.clone(), chat.model.clone()))
+            .collect()
+    }
+
+    /**
+    Finds every message containing `query`, ignoring case
+
+    Returns the chat uuid and message index of each match, oldest chat first,
+    indices count in chronological order like in `fork_chat_at`, the system prompt is not searched
+    */
+    pub fn search(&self, query: &str) -> Vec<(Uuid, usize)> {
+        let query = query.to_lowercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+        self.sorted_chats()
+            .flat_map(|(uuid, chat)| {
+                // history is stored newest first
+                chat.history
+                    .iter()
+                    .flat_map(|history| history.iter().rev())
+                    .enumerate()
+                    .filter(|(_, msg)| msg.content.text().to_lowercase().contains(&query))
+                    .map(|(index, _)| (*uuid, index))
+            })
+            .collect()
+    }
+
+    /// Chats ordered by creation, with the uuid breaking ties for a stable order
+    fn sorted_chats(&self) -> impl Iterator<Item = (&Uuid, &Chat)> {
+        let mut chats: Vec<_> = self.chats.iter().collect();
+        chats.sort_by_key(|(uuid, chat)| (chat.created_at, **uuid));
+        chats.into_iter()
+    }
+
+    /// Iterates over every chat in unspecified order, use `list_chats` for a stable order
+    pub fn iter(&self) -> impl Iterator<Item = (Uuid, &Chat)> {
+        self.chats.iter().map(|(uuid, chat)| (*uuid, chat))
+    }
+
+    /// Same as `iter` but allows modifying the chats
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (Uuid, &mut Chat)> {
+        self.chats.iter_mut().map(|(uuid, chat)| (*uuid, chat))
+    }
+
+    /**
+    Sets the title of a chat by uuid
+
+    Fails if chat does not exist
+    */
+    pub fn rename_chat(&mut self, chat_uuid: Uuid, title: String) -> crate::Result<()> {
+        let chat = self.get_chat(chat_uuid).ok_or(Error::ChatNotFound)?;
+        chat.title = Some(title);
+        Ok(())
+    }
+
     /**
     Gets chat by uuid
 
@@ -77,9 +224,9 @@ impl ChatManager {
 
     Fails if chat does not exist (which should never happen, if it does report it)
     */
-    pub fn set_current_chat(&mut self, chat_uuid: Uuid) -> anyhow::Result<()> {
+    pub fn set_current_chat(&mut self, chat_uuid: Uuid) -> crate::Result<()> {
         if !self.chat_exists(chat_uuid) {
-            return Err(anyhow!("chat does not exist"));
+            return Err(Error::ChatNotFound);
         }
 
         self.current_chat = chat_uuid;
@@ -104,23 +251,29 @@ impl ChatManager {
         ))
     }
 
-    /// Sends a completion in the current chat
+    /**
+    Empties the current chat's history, keeping its settings and system prompt
+
+    Fails if current chat does not exist
+    */
+    pub fn clear_current_chat_history(&mut self) -> crate::Result<()> {
+        let (_, current_chat) = self.get_current_chat().ok_or(Error::ChatNotFound)?;
+        current_chat.clear_history();
+        Ok(())
+    }
+
+    /// Sends a completion in the current chat and returns the assistant reply
     pub async fn send_current_chat_completion(
         &mut self,
-        api_key: &str,
+        client: &Client,
         msg: Completion,
-    ) -> anyhow::Result<()> {
+    ) -> crate::Result<Completion> {
         let (_, current_chat) = match self.get_current_chat() {
             Some(chat) => chat,
-            None => {
-                return Err(anyhow!(
-                    "failed to send message, current chat does not exist"
-                ))
-            }
+            None => return Err(Error::ChatNotFound),
         };
 
-        current_chat.send_completion(api_key, msg).await?;
-        Ok(())
+        current_chat.send_completion(client, msg).await
     }
 }
 
@@ -130,6 +283,36 @@ pub struct Chat {
     pub model: Model,
     pub global_params: CompletionParams,
     pub history: Option<VecDeque<Completion>>,
+    /// Kept apart from history and sent as the first message of every request
+    #[serde(default)]
+    system_prompt: Option<Completion>,
+    /// Most messages kept in history, older ones are trimmed before sending
+    #[serde(default)]
+    pub max_history_messages: Option<usize>,
+    /// Approximate token budget for history and system prompt, see `Chat::trim_history`
+    #[serde(default)]
+    pub max_history_tokens: Option<usize>,
+    /// Runs every message through moderation first and refuses to send flagged ones
+    #[serde(default)]
+    pub moderate_input: bool,
+    /// Decides which history messages are sent, the system prompt is always sent
+    #[serde(default)]
+    pub history_policy: HistoryPolicy,
+    /// Model used by `Chat::generate_title`, defaults to `DEFAULT_TITLE_MODEL`
+    #[serde(default)]
+    pub title_model: Option<Model>,
+    /// Seconds since the unix epoch the chat was created at, 0 for chats saved before it was tracked
+    #[serde(default)]
+    pub created_at: u64,
+    /// Finish reason of the latest response, `FinishReason::Length` means it was truncated
+    #[serde(default)]
+    pub last_finish_reason: Option<FinishReason>,
+    /// Answers a user message repeating the newest one with the existing reply instead of sending it again
+    #[serde(default)]
+    pub dedup: bool,
+    /// Tokens used by every request of this chat so far, see `Chat::total_usage`
+    #[serde(default)]
+    total_usage: Usage,
 }
 
 impl Chat {
@@ -137,46 +320,1274 @@ impl Chat {
         Self {
             title: None,
             model,
-            global_params: CompletionParams::new(512, 0.7, 0.7, 0.7, false),
+            global_params: CompletionParams::default(),
             history: None,
+            system_prompt: None,
+            max_history_messages: None,
+            max_history_tokens: None,
+            moderate_input: false,
+            history_policy: HistoryPolicy::default(),
+            title_model: None,
+            created_at: unix_now(),
+            last_finish_reason: None,
+            dedup: false,
+            total_usage: Usage::default(),
         }
     }
 
+    /// Configures a chat in one expression, e.g. `Chat::builder(model).title("x").history(true).build()`
+    pub fn builder(model: Model) -> ChatBuilder {
+        ChatBuilder {
+            chat: Self::new(model),
+            params: None,
+        }
+    }
+
+    /// Creates a chat with history enabled and seeded with messages given in chronological order
+    pub fn with_messages(model: Model, messages: Vec<Completion>) -> Self {
+        let mut chat = Self::new(model);
+        chat.append_messages(messages);
+        chat
+    }
+
+    /// Adds messages given in chronological order after the current history, enabling history if needed
+    pub fn append_messages(&mut self, messages: Vec<Completion>) -> &mut Self {
+        let history = self.history.get_or_insert_with(VecDeque::new);
+        // history is stored newest first
+        for msg in messages {
+            history.push_front(msg);
+        }
+        self
+    }
+
+    /**
+    Inserts a message so it ends up at `index` in chronological order, shifting later messages back
+
+    0 inserts before the oldest message, the history length appends after the newest one
+
+    Fails if history is disabled or the index is past the end of history
+    */
+    pub fn insert_message(&mut self, index: usize, msg: Completion) -> crate::Result<()> {
+        let history = self
+            .history
+            .as_mut()
+            .ok_or_else(|| Error::InvalidInput("chat has no history".to_string()))?;
+        // history is stored newest first
+        let position = history
+            .len()
+            .checked_sub(index)
+            .ok_or_else(|| Error::InvalidInput(format!("index {index} is out of bounds")))?;
+        history.insert(position, msg);
+        Ok(())
+    }
+
     pub fn with_title(&mut self, title: String) -> &mut Self {
         self.title = Some(title);
         self
     }
 
+    pub fn with_title_model(&mut self, model: Model) -> &mut Self {
+        self.title_model = Some(model);
+        self
+    }
+
     pub fn with_history(&mut self) -> &mut Self {
         self.history = Some(VecDeque::new());
         self
     }
 
+    /// Returns the newest message of the role in history, None if there is none or history is disabled
+    pub fn last_message_by_role(&self, role: CompletionRole) -> Option<&Completion> {
+        // history is stored newest first
+        self.history
+            .iter()
+            .flatten()
+            .find(|msg| msg.get_role() == role)
+    }
+
+    pub fn last_user_message(&self) -> Option<&Completion> {
+        self.last_message_by_role(CompletionRole::USER)
+    }
+
+    pub fn last_assistant_message(&self) -> Option<&Completion> {
+        self.last_message_by_role(CompletionRole::ASSISTANT)
+    }
+
+    /// Returns how many messages are in history, the system prompt is not counted
+    pub fn message_count(&self) -> usize {
+        self.history.as_ref().map_or(0, VecDeque::len)
+    }
+
+    /// Empties history while keeping model, params and system prompt, does nothing if history is disabled
+    pub fn clear_history(&mut self) {
+        if let Some(history) = self.history.as_mut() {
+            history.clear();
+        }
+        self.last_finish_reason = None;
+        self.total_usage = Usage::default();
+    }
+
+    /**
+    Returns the tokens used by every completion sent from this chat, e.g. to show the conversation's cost
+
+    Only counts what the API reported, streamed replies only report usage with `CompletionParams::stream_usage`.
+    Reset by `clear_history`
+    */
+    pub fn total_usage(&self) -> Usage {
+        self.total_usage
+    }
+
+    /// Returns if history is enabled, without it only the latest message is sent and nothing is kept
+    pub fn has_history(&self) -> bool {
+        self.history.is_some()
+    }
+
+    /// Sets the system prompt sent before every request, replacing any previous one
+    pub fn with_system_prompt(&mut self, prompt: &str) -> &mut Self {
+        self.system_prompt = Some(Completion::new(CompletionRole::SYSTEM, prompt));
+        self
+    }
+
+    pub fn system_prompt(&self) -> Option<&Completion> {
+        self.system_prompt.as_ref()
+    }
+
+    /**
+    Replaces `global_params` after validating them, prefer it over assigning the field directly
+
+    Fails if any parameter is outside of the range the API accepts
+    or `max_tokens` exceeds the model's known context window
+    */
+    pub fn set_params(&mut self, params: CompletionParams) -> crate::Result<&mut Self> {
+        params.validate()?;
+        if let Some(context_length) = self.model.context_length() {
+            if params.max_tokens > context_length {
+                return Err(Error::InvalidInput(format!(
+                    "max tokens {} exceed the context window of {} tokens of {}",
+                    params.max_tokens, context_length, self.model.name
+                )));
+            }
+        }
+        self.global_params = params;
+        Ok(self)
+    }
+
+    /// Enables moderating every message before it's sent, see `Chat::send_completion`
+    pub fn with_moderation(&mut self) -> &mut Self {
+        self.moderate_input = true;
+        self
+    }
+
+    /// Enables answering repeats of the newest user message with its existing reply, e.g. on double submits
+    pub fn with_dedup(&mut self) -> &mut Self {
+        self.dedup = true;
+        self
+    }
+
+    pub fn with_history_policy(&mut self, history_policy: HistoryPolicy) -> &mut Self {
+        self.history_policy = history_policy;
+        self
+    }
+
+    pub fn with_max_history_messages(&mut self, max: usize) -> &mut Self {
+        self.max_history_messages = Some(max);
+        self
+    }
+
+    pub fn with_max_history_tokens(&mut self, max: usize) -> &mut Self {
+        self.max_history_tokens = Some(max);
+        self
+    }
+
+    /**
+    Drops the oldest messages until history fits the configured limits
+
+    Tokens are estimated with `count_tokens` plus a small per message overhead,
+    the system prompt counts towards the budget but is never dropped and neither is the newest message.
+    Tool results left without the assistant message that called the tool are dropped as well
+    */
+    pub fn trim_history(&mut self) {
+        let Some(history) = self.history.as_mut() else {
+            return;
+        };
+
+        if let Some(max) = self.max_history_messages {
+            history.truncate(max.max(1));
+        }
+
+        if let Some(max) = self.max_history_tokens {
+            let estimate = |msg: &Completion| estimate_tokens(msg, &self.model);
+            let mut tokens: usize = self.system_prompt.iter().map(estimate).sum();
+            tokens += history.iter().map(estimate).sum::<usize>();
+            while tokens > max && history.len() > 1 {
+                tokens -= estimate(&history.pop_back().unwrap());
+            }
+        }
+
+        // tool results are rejected without the assistant message calling the tool before them
+        while history.len() > 1
+            && history.back().map(Completion::get_role) == Some(CompletionRole::TOOL)
+        {
+            history.pop_back();
+        }
+    }
+
+    /**
+    Estimates how many tokens the system prompt and history take up when sent
+
+    Meant for showing context usage, compare it to `ModelInfo::context_length`
+    */
+    pub fn estimated_prompt_tokens(&self) -> usize {
+        self.system_prompt
+            .iter()
+            .chain(self.history.iter().flatten())
+            .map(|msg| estimate_tokens(msg, &self.model))
+            .sum()
+    }
+
     /**
     Sends a completion and adds it to history as first element
 
-    Returns Ok if message was sent successfully and adds response to history as first element
+    Returns the assistant reply and adds it to history as first element
 
     Fails if sending a message returned an error and adds error message to history as first element
+
+    With moderation enabled flagged messages fail with `Error::Flagged` and are not added to history,
+    with dedup enabled a repeat of the newest user message returns its reply without sending anything
     */
-    pub async fn send_completion(&mut self, api_key: &str, msg: Completion) -> anyhow::Result<()> {
-        let res = send_completion(
-            api_key,
-            &self.model,
-            msg,
-            &self.global_params,
-            &mut self.history,
-        )
-        .await;
+    pub async fn send_completion(
+        &mut self,
+        client: &Client,
+        msg: Completion,
+    ) -> crate::Result<Completion> {
+        if let Some(reply) = self.duplicate_reply(&msg) {
+            return Ok(reply);
+        }
+        self.check_moderation(client, &msg).await?;
+        self.send_moderated(client, msg).await
+    }
+
+    /**
+    Same as `send_completion` but sends this message to another model, the chat's model stays unchanged
+
+    The reply is added to the same history
+    */
+    pub async fn send_completion_with_model(
+        &mut self,
+        client: &Client,
+        msg: Completion,
+        model: &Model,
+    ) -> crate::Result<Completion> {
+        if let Some(reply) = self.duplicate_reply(&msg) {
+            return Ok(reply);
+        }
+        self.check_moderation(client, &msg).await?;
+        let res = self
+            .send_moderated_response(client, msg, Some(model))
+            .await?;
+        let choice = res.choices.into_iter().next().ok_or(Error::EmptyChoices)?;
+        Ok(choice.message)
+    }
+
+    /**
+    Same as `send_completion` but also returns the other choices if the API returned several
+
+    Only the first choice is added to history and returned first, the alternatives are not persisted
+    */
+    pub async fn send_completion_with_alternatives(
+        &mut self,
+        client: &Client,
+        msg: Completion,
+    ) -> crate::Result<(Completion, Vec<Completion>)> {
+        if let Some(reply) = self.duplicate_reply(&msg) {
+            return Ok((reply, Vec::new()));
+        }
+        self.check_moderation(client, &msg).await?;
+        let mut choices = self
+            .send_moderated_response(client, msg, None)
+            .await?
+            .choices
+            .into_iter()
+            .map(|choice| choice.message);
+        let chosen = choices.next().unwrap();
+        Ok((chosen, choices.collect()))
+    }
+
+    /// Same as `send_completion` for a message that already passed moderation
+    async fn send_moderated(
+        &mut self,
+        client: &Client,
+        msg: Completion,
+    ) -> crate::Result<Completion> {
+        let res = self.send_moderated_response(client, msg, None).await?;
+        let choice = res.choices.into_iter().next().ok_or(Error::EmptyChoices)?;
+        Ok(choice.message)
+    }
+
+    /// `model` overrides the chat's model for this request only
+    async fn send_moderated_response(
+        &mut self,
+        client: &Client,
+        msg: Completion,
+        model: Option<&Model>,
+    ) -> crate::Result<CompletionResponse> {
+        let res = self.complete(client, msg, model).await;
         if res.is_err() {
             add_history(
                 &mut self.history,
-                Completion::new(
-                    CompletionRole::AI,
-                    "An error occured while sending the message",
-                ),
+                Completion::new(CompletionRole::ASSISTANT, ERROR_MESSAGE),
             );
         }
         res
     }
+
+    /**
+    Streams a completion, calling `on_delta` with every piece of content as it arrives
+
+    The accumulated reply including any tool calls is returned and added to history once the stream ends,
+    if it fails midway whatever was received so far is added instead
+
+    Fails if the request or the stream failed or neither content nor tool calls arrived,
+    like `send_completion` an error message is added to history if nothing was received.
+    A repeat deduped like in `send_completion` returns the existing reply without calling `on_delta`
+    */
+    pub async fn send_completion_stream(
+        &mut self,
+        client: &Client,
+        msg: Completion,
+        mut on_delta: impl FnMut(&str),
+    ) -> crate::Result<Completion> {
+        if let Some(reply) = self.duplicate_reply(&msg) {
+            return Ok(reply);
+        }
+        self.check_moderation(client, &msg).await?;
+        self.add_message(msg.clone());
+        self.trim_history();
+        self.last_finish_reason = None;
+        let messages = self.outbound_messages(msg);
+
+        let mut stream = match client
+            .send_messages_stream(&self.model, &messages, &self.global_params)
+            .await
+        {
+            Ok(stream) => stream,
+            Err(err) => {
+                add_history(
+                    &mut self.history,
+                    Completion::new(CompletionRole::ASSISTANT, ERROR_MESSAGE),
+                );
+                return Err(err);
+            }
+        };
+
+        let mut failed = None;
+        while let Some(delta) = stream.next().await {
+            match delta {
+                Ok(delta) => on_delta(&delta),
+                Err(err) => {
+                    failed = Some(err);
+                    break;
+                }
+            }
+        }
+
+        // the stream ended either way, finish only assembles what was received
+        let res = stream.finish().await;
+        let reply = match &res {
+            Ok(res) => {
+                let choice = &res.choices[0];
+                self.last_finish_reason = choice.finish_reason.clone();
+                self.add_usage(res.usage);
+                choice.message.clone()
+            }
+            Err(_) => Completion::new(CompletionRole::ASSISTANT, ERROR_MESSAGE),
+        };
+        add_history(&mut self.history, reply.clone());
+        match failed {
+            Some(err) => Err(err),
+            None => res.map(|_| reply),
+        }
+    }
+
+    /**
+    Asks a cheap model to summarize the first exchange into a short title and stores it as title
+
+    Does nothing if history is disabled or empty, or the model returned an empty title
+
+    Fails if the request failed, history is left untouched either way
+    */
+    pub async fn generate_title(&mut self, client: &Client) -> crate::Result<()> {
+        let Some(history) = &self.history else {
+            return Ok(());
+        };
+        // history is stored newest first, the first exchange is at the back
+        let exchange: Vec<String> = history
+            .iter()
+            .rev()
+            .filter_map(|msg| match msg.get_role() {
+                CompletionRole::USER => Some(format!("User: {}", msg.content.text())),
+                CompletionRole::ASSISTANT => Some(format!("Assistant: {}", msg.content.text())),
+                _ => None,
+            })
+            .take(2)
+            .collect();
+        if exchange.is_empty() {
+            return Ok(());
+        }
+
+        let messages = [
+            Completion::new(CompletionRole::SYSTEM, TITLE_PROMPT),
+            Completion::new(CompletionRole::USER, &exchange.join("\n")),
+        ];
+        let model = self
+            .title_model
+            .clone()
+            .unwrap_or_else(|| Model::from(DEFAULT_TITLE_MODEL.to_string()));
+        let params = CompletionParams::new(16, 0.0, 1.0, 0.3, false);
+        let res = match client.send_messages(&model, &messages, &params).await {
+            Ok(res) => res,
+            Err(Error::EmptyContent) => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        self.add_usage(res.usage);
+
+        let reply = &res.choices.first().ok_or(Error::EmptyChoices)?.message;
+        let title = clean_title(&reply.content.text());
+        if !title.is_empty() {
+            self.title = Some(title);
+        }
+        Ok(())
+    }
+
+    /**
+    Replaces every message older than the `keep_recent` newest ones with a single summary of them
+
+    The summary is generated by the chat's model and kept as system message, so the gist of long
+    conversations survives instead of being cut off by `trim_history`. The system prompt is untouched
+
+    Returns false without sending anything if history is disabled or there is nothing older to compress,
+    fails if the request failed in which case history is unchanged
+    */
+    pub async fn compress_history(
+        &mut self,
+        client: &Client,
+        keep_recent: usize,
+    ) -> crate::Result<bool> {
+        let Some(history) = &self.history else {
+            return Ok(false);
+        };
+        if history.len() <= keep_recent {
+            return Ok(false);
+        }
+        // history is stored newest first, the oldest messages are at the back
+        let transcript: Vec<String> = history
+            .iter()
+            .skip(keep_recent)
+            .rev()
+            .filter(|msg| !is_error_message(msg))
+            .map(|msg| format!("{}: {}", role_label(msg.get_role()), msg.content.text()))
+            .collect();
+
+        let messages = [
+            Completion::new(CompletionRole::SYSTEM, SUMMARY_PROMPT),
+            Completion::new(CompletionRole::USER, &transcript.join("\n")),
+        ];
+        let params = CompletionParams {
+            stream: false,
+            tools: None,
+            tool_choice: None,
+            parallel_tool_calls: None,
+            response_format: None,
+            n: None,
+            ..self.global_params.clone()
+        };
+        let res = client
+            .send_messages(&self.model, &messages, &params)
+            .await?;
+        self.add_usage(res.usage);
+        let reply = &res.choices.first().ok_or(Error::EmptyChoices)?.message;
+        let summary = reply.content.text();
+
+        let history = self.history.as_mut().unwrap();
+        history.truncate(keep_recent);
+        history.push_back(Completion::new(
+            CompletionRole::SYSTEM,
+            &format!("Summary of the earlier conversation: {}", summary.trim()),
+        ));
+        Ok(true)
+    }
+
+    /**
+    Continues the latest assistant reply if it was cut off by `max_tokens`, returning the combined text
+
+    The continuation is appended to that reply in history instead of being added as a new turn,
+    call it again while `last_finish_reason` is still `FinishReason::Length` for longer outputs
+
+    Fails if history is disabled, the latest reply was not truncated or the request failed,
+    history is unchanged on failure
+    */
+    pub async fn continue_response(&mut self, client: &Client) -> crate::Result<String> {
+        let not_truncated = || Error::InvalidInput("latest reply was not truncated".to_string());
+        if self.last_finish_reason != Some(FinishReason::Length) {
+            return Err(not_truncated());
+        }
+        let history = self.history.as_ref().ok_or_else(not_truncated)?;
+        if history.front().map(Completion::get_role) != Some(CompletionRole::ASSISTANT) {
+            return Err(not_truncated());
+        }
+
+        let mut messages: Vec<Completion> = self.system_prompt.iter().cloned().collect();
+        messages.extend(self.sent_history(history).into_iter().cloned());
+        messages.push(Completion::new(CompletionRole::USER, CONTINUE_PROMPT));
+        let params = CompletionParams {
+            stream: false,
+            n: None,
+            ..self.global_params.clone()
+        };
+        let res = client
+            .send_messages(&self.model, &messages, &params)
+            .await?;
+        self.add_usage(res.usage);
+
+        let choice = res.choices.into_iter().next().ok_or(Error::EmptyChoices)?;
+        self.last_finish_reason = choice.finish_reason;
+        let reply = self.history.as_mut().and_then(VecDeque::front_mut).unwrap();
+        let text = format!("{}{}", reply.content.text(), choice.message.content.text());
+        reply.content = Content::from(text.as_str());
+        Ok(text)
+    }
+
+    /**
+    Discards the latest assistant reply and resends the user message it answered, returning the new reply
+
+    Tool calls and results following that user message are discarded as well,
+    change `global_params` beforehand (e.g. temperature or seed) to get a different reply
+
+    Fails if history is disabled or the newest message is not an assistant reply to a user message
+    */
+    pub async fn regenerate(&mut self, client: &Client) -> crate::Result<Completion> {
+        let no_reply = || Error::InvalidInput("no assistant message to regenerate".to_string());
+        let history = self.history.as_mut().ok_or_else(no_reply)?;
+        if history.front().map(Completion::get_role) != Some(CompletionRole::ASSISTANT) {
+            return Err(no_reply());
+        }
+        // history is stored newest first, so the user message is the first one found from the front
+        let user = history
+            .iter()
+            .position(|msg| msg.get_role() == CompletionRole::USER)
+            .ok_or_else(no_reply)?;
+
+        let msg = history[user].clone();
+        // check before discarding anything so a flagged message leaves history untouched
+        self.check_moderation(client, &msg).await?;
+
+        self.history.as_mut().unwrap().drain(..=user);
+        // the message is resent on purpose, so it must not be deduped against an older identical one
+        self.send_moderated(client, msg).await
+    }
+
+    /**
+    Replaces the content of a previous user message and resends it, returning the new reply
+
+    `index` counts in chronological order, 0 is the oldest message in history,
+    every message after the edited one is discarded
+
+    Fails if history is disabled, the index is out of bounds or not a user message
+    */
+    pub async fn edit_message(
+        &mut self,
+        index: usize,
+        new_content: &str,
+        client: &Client,
+    ) -> crate::Result<Completion> {
+        let history = self
+            .history
+            .as_mut()
+            .ok_or_else(|| Error::InvalidInput("chat has no history".to_string()))?;
+        // history is stored newest first
+        let position = history
+            .len()
+            .checked_sub(index)
+            .and_then(|len| len.checked_sub(1))
+            .ok_or_else(|| Error::InvalidInput(format!("no message at index {index}")))?;
+        if history[position].get_role() != CompletionRole::USER {
+            return Err(Error::InvalidInput(format!(
+                "message at index {index} is not a user message"
+            )));
+        }
+
+        let mut msg = history[position].clone();
+        msg.content = Content::from(new_content);
+        // check before discarding anything so a flagged edit leaves history untouched
+        self.check_moderation(client, &msg).await?;
+
+        self.history.as_mut().unwrap().drain(..=position);
+        self.send_moderated(client, msg).await
+    }
+
+    /**
+    Renders the system prompt and history in chronological order as markdown
+
+    The title becomes a heading and the model is added as footer, content is kept as is
+    so code fences stay intact
+    */
+    pub fn to_markdown(&self) -> String {
+        let mut md = String::new();
+        if let Some(title) = &self.title {
+            md.push_str(&format!("# {title}\n\n"));
+        }
+
+        // history is stored newest first
+        let history = self.history.iter().flat_map(|history| history.iter().rev());
+        for msg in self.system_prompt.iter().chain(history) {
+            let role = match msg.get_role() {
+                CompletionRole::USER => "You",
+                CompletionRole::SYSTEM => "System",
+                CompletionRole::ASSISTANT => "Assistant",
+                CompletionRole::TOOL => "Tool",
+            };
+            md.push_str(&format!(
+                "**{role}:**\n\n{}\n\n",
+                msg.content.text().trim_end()
+            ));
+        }
+
+        md.push_str(&format!("---\n\n_Model: {}_\n", self.model.name));
+        md
+    }
+
+    /// Fails with `Error::Flagged` if moderation is enabled and flags the message
+    async fn check_moderation(&self, client: &Client, msg: &Completion) -> crate::Result<()> {
+        if !self.moderate_input {
+            return Ok(());
+        }
+        let res = client.moderate(&msg.content.text()).await?;
+        if res.flagged {
+            return Err(Error::Flagged {
+                categories: res.flagged_categories(),
+            });
+        }
+        Ok(())
+    }
+
+    /**
+    Returns the reply to the newest user message if dedup is enabled and `msg` repeats that message
+
+    None if the newest message isn't a reply, e.g. because sending the previous message failed
+    */
+    fn duplicate_reply(&self, msg: &Completion) -> Option<Completion> {
+        if !self.dedup || msg.get_role() != CompletionRole::USER {
+            return None;
+        }
+        // history is stored newest first, so the user message is older than the reply
+        let reply = self.history.as_ref()?.front().filter(|front| {
+            front.get_role() == CompletionRole::ASSISTANT && !is_error_message(front)
+        })?;
+        let user = self.last_user_message()?;
+        (user.content == msg.content).then(|| reply.clone())
+    }
+
+    /// Adds the usage of a response to `total_usage`, responses that didn't report any are not counted
+    fn add_usage(&mut self, usage: Option<Usage>) {
+        if let Some(usage) = usage {
+            self.total_usage += usage;
+        }
+    }
+
+    /// Adds a message about to be sent to history, unless dedup is enabled and it repeats the newest one
+    fn add_message(&mut self, msg: Completion) {
+        let repeated = self
+            .history
+            .as_ref()
+            .and_then(VecDeque::front)
+            .is_some_and(|front| {
+                front.get_role() == msg.get_role() && front.content == msg.content
+            });
+        if !(self.dedup && repeated) {
+            add_history(&mut self.history, msg);
+        }
+    }
+
+    /// Sends the system prompt and history (or only msg without history) and adds the response to history
+    async fn complete(
+        &mut self,
+        client: &Client,
+        msg: Completion,
+        model: Option<&Model>,
+    ) -> crate::Result<CompletionResponse> {
+        self.add_message(msg.clone());
+        self.trim_history();
+        let messages = self.outbound_messages(msg);
+        let model = model.unwrap_or(&self.model);
+        let res = client
+            .send_messages(model, &messages, &self.global_params)
+            .await?;
+        self.add_usage(res.usage);
+        let choice = res.choices.first().ok_or(Error::EmptyChoices)?;
+        self.last_finish_reason = choice.finish_reason.clone();
+        add_history(&mut self.history, choice.message.clone());
+        Ok(res)
+    }
+
+    /// Returns the messages to send in chronological order, history is expected to already contain msg
+    fn outbound_messages(&self, msg: Completion) -> Vec<Completion> {
+        let mut messages: Vec<Completion> = self.system_prompt.iter().cloned().collect();
+        match &self.history {
+            Some(history) => messages.extend(self.sent_history(history).into_iter().cloned()),
+            None => messages.push(msg),
+        }
+        messages
+    }
+
+    /**
+    Returns the history messages to send in chronological order according to the history policy
+
+    Error messages added on failed sends are left out, the model never said them
+    */
+    fn sent_history<'a>(&self, history: &'a VecDeque<Completion>) -> Vec<&'a Completion> {
+        // history is stored newest first, the api expects chronological order
+        let history = history.iter().rev().filter(|msg| !is_error_message(msg));
+        self.history_policy.apply(history.collect())
+    }
+}
+
+/// Builder for `Chat`, every setting that isn't set keeps the default of `Chat::new`
+#[derive(Debug, Clone)]
+pub struct ChatBuilder {
+    chat: Chat,
+    params: Option<CompletionParams>,
+}
+
+impl ChatBuilder {
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.chat.title = Some(title.into());
+        self
+    }
+
+    pub fn title_model(mut self, model: Model) -> Self {
+        self.chat.title_model = Some(model);
+        self
+    }
+
+    /// Enables or disables history, without it only the latest message is sent
+    pub fn history(mut self, history: bool) -> Self {
+        self.chat.history = history.then(VecDeque::new);
+        self
+    }
+
+    pub fn system_prompt(mut self, prompt: &str) -> Self {
+        self.chat.with_system_prompt(prompt);
+        self
+    }
+
+    /// Validated by `build`, see `Chat::set_params`
+    pub fn params(mut self, params: CompletionParams) -> Self {
+        self.params = Some(params);
+        self
+    }
+
+    pub fn moderation(mut self, moderate_input: bool) -> Self {
+        self.chat.moderate_input = moderate_input;
+        self
+    }
+
+    pub fn dedup(mut self, dedup: bool) -> Self {
+        self.chat.dedup = dedup;
+        self
+    }
+
+    pub fn history_policy(mut self, history_policy: HistoryPolicy) -> Self {
+        self.chat.history_policy = history_policy;
+        self
+    }
+
+    pub fn max_history_messages(mut self, max: usize) -> Self {
+        self.chat.max_history_messages = Some(max);
+        self
+    }
+
+    pub fn max_history_tokens(mut self, max: usize) -> Self {
+        self.chat.max_history_tokens = Some(max);
+        self
+    }
+
+    /// Fails if the params are outside of the range the API accepts or exceed the model's context window
+    pub fn build(mut self) -> crate::Result<Chat> {
+        if let Some(params) = self.params {
+            self.chat.set_params(params)?;
+        }
+        Ok(self.chat)
+    }
+}
+
+/// Returns the seconds since the unix epoch, 0 if the clock is set before it
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+/// Added to history as assistant message when sending a message failed, it's never sent to the model
+const ERROR_MESSAGE: &str = "An error occured while sending the message";
+
+/// Model `Chat::generate_title` uses unless `Chat::title_model` is set
+pub const DEFAULT_TITLE_MODEL: &str = "gpt-4o-mini";
+
+const TITLE_PROMPT: &str = "Summarize the conversation in a short title of at most six words. \
+Respond with the title only, without quotes or punctuation at the end.";
+
+const CONTINUE_PROMPT: &str =
+    "Continue exactly where your previous message stopped, without repeating any of it.";
+
+const SUMMARY_PROMPT: &str =
+    "Summarize the following conversation so it can be continued without it. \
+Keep every fact, decision and open question, respond with the summary only.";
+
+/// Returns if the message is the placeholder added to history when sending failed
+fn is_error_message(msg: &Completion) -> bool {
+    msg.get_role() == CompletionRole::ASSISTANT && msg.content.text() == ERROR_MESSAGE
+}
+
+fn role_label(role: CompletionRole) -> &'static str {
+    match role {
+        CompletionRole::USER => "User",
+        CompletionRole::ASSISTANT => "Assistant",
+        CompletionRole::SYSTEM => "System",
+        CompletionRole::TOOL => "Tool",
+    }
+}
+
+/// Collapses the model's output to a single line and strips surrounding quotes
+fn clean_title(title: &str) -> String {
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    title
+        .trim_matches(|c: char| {
+            matches!(c, '"' | '\'' | '`' | '“' | '”' | '*') || c.is_whitespace()
+        })
+        .to_string()
+}
+
+/// Roughly estimates how many tokens a message takes up, every message adds a few tokens of overhead for its role
+fn estimate_tokens(msg: &Completion, model: &Model) -> usize {
+    const MESSAGE_OVERHEAD: usize = 4;
+    count_tokens(&msg.content.text(), model) + MESSAGE_OVERHEAD
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::ToolCall;
+
+    fn model() -> Model {
+        Model::from("gpt-4o".to_string())
+    }
+
+    /// Unique path in the temp dir, removed again by the caller
+    fn temp_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("aimlapi-test-{}.json", Uuid::new_v4()))
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mut manager = ChatManager::new();
+        let first = manager.create_new_chat(model());
+        let second = manager.create_new_chat(Model::from("gpt-4o-mini".to_string()));
+        let params = CompletionParams::builder()
+            .max_tokens(256)
+            .temperature(0.2)
+            .seed(7)
+            .stop(vec!["END".to_string()])
+            .build()
+            .unwrap();
+        let chat = manager.get_chat(second).unwrap();
+        chat.with_title("Trip".to_string())
+            .with_history()
+            .with_system_prompt("be brief")
+            .set_params(params)
+            .unwrap();
+        chat.append_messages(vec![Completion::user("hello"), Completion::assistant("hi")]);
+        manager.set_current_chat(second).unwrap();
+
+        let path = temp_path();
+        manager.save_to_path(&path).unwrap();
+        let mut loaded = ChatManager::load_from_path(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&loaded).unwrap(),
+            serde_json::to_value(&manager).unwrap()
+        );
+        assert!(loaded.chat_exists(first));
+        let (current, chat) = loaded.get_current_chat().unwrap();
+        assert_eq!(current, second);
+        assert_eq!(chat.title.as_deref(), Some("Trip"));
+        assert_eq!(chat.system_prompt().unwrap().content.text(), "be brief");
+        assert_eq!(chat.global_params.max_tokens, 256);
+        assert_eq!(chat.global_params.seed, Some(7));
+        assert_eq!(chronological(chat), ["hello", "hi"]);
+    }
+
+    #[test]
+    fn load_or_default_without_file() {
+        let manager = ChatManager::load_or_default(temp_path()).unwrap();
+        assert!(manager.list_chats().is_empty());
+    }
+
+    /// Returns the history contents in chronological order
+    fn chronological(chat: &Chat) -> Vec<String> {
+        chat.history
+            .iter()
+            .flatten()
+            .rev()
+            .map(|msg| msg.content.text().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn insert_message_keeps_chronological_order() {
+        let mut chat = Chat::with_messages(
+            model(),
+            vec![Completion::user("b"), Completion::assistant("d")],
+        );
+
+        chat.insert_message(0, Completion::system("a")).unwrap();
+        chat.insert_message(2, Completion::assistant("c")).unwrap();
+        chat.insert_message(4, Completion::user("e")).unwrap();
+
+        assert_eq!(chronological(&chat), ["a", "b", "c", "d", "e"]);
+        assert!(chat.insert_message(6, Completion::user("f")).is_err());
+        assert!(Chat::new(model())
+            .insert_message(0, Completion::user("a"))
+            .is_err());
+    }
+
+    /// Chronological history with a tool call turn in the middle
+    fn tool_call_chat() -> Chat {
+        let mut call = Completion::assistant("");
+        call.tool_calls = Some(vec![ToolCall {
+            id: "call_1".to_string(),
+            name: "get_weather".to_string(),
+            arguments: "{}".to_string(),
+        }]);
+        Chat::with_messages(
+            model(),
+            vec![
+                Completion::user("what is the weather"),
+                call,
+                Completion::tool("call_1", "sunny"),
+                Completion::assistant("it is sunny"),
+                Completion::user("thanks"),
+            ],
+        )
+    }
+
+    #[test]
+    fn trimming_never_keeps_a_tool_result_without_its_call() {
+        let mut chat = tool_call_chat();
+        // the limit cuts right between the tool call and its result
+        chat.with_max_history_messages(3).trim_history();
+        assert_eq!(chronological(&chat), ["it is sunny", "thanks"]);
+
+        let mut chat = tool_call_chat();
+        let budget = ["sunny", "it is sunny", "thanks"]
+            .map(|text| estimate_tokens(&Completion::user(text), &chat.model))
+            .iter()
+            .sum();
+        chat.with_max_history_tokens(budget).trim_history();
+        assert_eq!(chronological(&chat), ["it is sunny", "thanks"]);
+    }
+
+    /// Tests sending through a `MockTransport`
+    #[cfg(feature = "test-util")]
+    mod sending {
+        use serde_json::{json, Value};
+
+        use super::*;
+        use crate::{mock::MockTransport, retry::RetryPolicy};
+
+        fn mock_client() -> (MockTransport, Client) {
+            let mock = MockTransport::new();
+            let client = Client::new("key")
+                .with_retry_policy(RetryPolicy::none())
+                .with_transport(mock.clone());
+            (mock, client)
+        }
+
+        fn reply(content: &str) -> Value {
+            json!({
+                "choices": [{
+                    "message": { "role": "assistant", "content": content },
+                    "finish_reason": "stop"
+                }]
+            })
+        }
+
+        /// Returns the `messages` of the request at `index` as `(role, content)` pairs
+        fn sent_messages(mock: &MockTransport, index: usize) -> Vec<(String, String)> {
+            let body = mock.requests()[index].json().unwrap();
+            body["messages"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|msg| {
+                    let role = msg["role"].as_str().unwrap().to_string();
+                    let content = msg["content"].as_str().unwrap_or_default().to_string();
+                    (role, content)
+                })
+                .collect()
+        }
+
+        #[tokio::test]
+        async fn stream_with_only_tool_calls_keeps_them() {
+            let (mock, client) = mock_client();
+            mock.push_response(
+                200,
+                concat!(
+                    "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\",\"tool_calls\":[{\"index\":0,",
+                    "\"id\":\"call_1\",\"type\":\"function\",\"function\":{\"name\":\"get_weather\",",
+                    "\"arguments\":\"{\\\"city\\\":\"}}]}}]}\n\n",
+                    "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":",
+                    "{\"arguments\":\"\\\"Paris\\\"}\"}}]},\"finish_reason\":\"tool_calls\"}]}\n\n",
+                    "data: [DONE]\n\n",
+                ),
+            );
+            let mut chat = Chat::new(model());
+            chat.with_history();
+
+            let reply = chat
+                .send_completion_stream(&client, Completion::user("weather in paris?"), |_| {})
+                .await
+                .unwrap();
+
+            let tool_calls = reply.tool_calls.as_deref().unwrap();
+            assert_eq!(tool_calls.len(), 1);
+            assert_eq!(tool_calls[0].name, "get_weather");
+            assert_eq!(tool_calls[0].arguments, "{\"city\":\"Paris\"}");
+            assert_eq!(chat.last_finish_reason, Some(FinishReason::ToolCalls));
+            let stored = chat.last_assistant_message().unwrap();
+            assert_eq!(stored.tool_calls, reply.tool_calls);
+        }
+
+        #[tokio::test]
+        async fn dedup_answers_a_double_send_with_the_existing_reply() {
+            let (mock, client) = mock_client();
+            mock.push_json(200, reply("hi there"));
+            let mut chat = Chat::new(model());
+            chat.with_history().with_dedup();
+
+            let first = chat
+                .send_completion(&client, Completion::user("hello"))
+                .await
+                .unwrap();
+            let second = chat
+                .send_completion(&client, Completion::user("hello"))
+                .await
+                .unwrap();
+
+            assert_eq!(second.content, first.content);
+            assert_eq!(mock.requests().len(), 1);
+            assert_eq!(chat.message_count(), 2);
+        }
+
+        #[tokio::test]
+        async fn dedup_resends_after_a_failed_send() {
+            let (mock, client) = mock_client();
+            mock.push_json(500, json!({ "error": "boom" }));
+            mock.push_json(200, reply("hi there"));
+            let mut chat = Chat::new(model());
+            chat.with_history().with_dedup();
+
+            assert!(chat
+                .send_completion(&client, Completion::user("hello"))
+                .await
+                .is_err());
+            let reply = chat
+                .send_completion(&client, Completion::user("hello"))
+                .await
+                .unwrap();
+
+            assert_eq!(reply.content.text(), "hi there");
+            assert_eq!(mock.requests().len(), 2);
+        }
+
+        #[tokio::test]
+        async fn flagged_regenerate_leaves_history_untouched() {
+            let (mock, client) = mock_client();
+            mock.push_json(200, reply("hi there"));
+            mock.push_json(
+                200,
+                json!({ "results": [{ "flagged": true, "categories": { "hate": true } }] }),
+            );
+            let mut chat = Chat::new(model());
+            chat.with_history();
+            chat.send_completion(&client, Completion::user("hello"))
+                .await
+                .unwrap();
+            chat.with_moderation();
+
+            let res = chat.regenerate(&client).await;
+
+            assert!(matches!(res, Err(Error::Flagged { .. })));
+            assert_eq!(chronological(&chat), ["hello", "hi there"]);
+            assert_eq!(mock.requests().len(), 2);
+        }
+
+        #[tokio::test]
+        async fn total_usage_sums_plain_and_streamed_turns() {
+            let (mock, client) = mock_client();
+            let mut plain = reply("hi");
+            plain["usage"] =
+                json!({ "prompt_tokens": 3, "completion_tokens": 1, "total_tokens": 4 });
+            mock.push_json(200, plain);
+            mock.push_response(
+                200,
+                concat!(
+                    "data: {\"choices\":[{\"delta\":{\"content\":\"sure\"}}]}\n\n",
+                    "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":6,\"completion_tokens\":2,",
+                    "\"total_tokens\":8}}\n\n",
+                    "data: [DONE]\n\n",
+                ),
+            );
+            mock.push_json(200, reply("no usage reported"));
+            let mut chat = Chat::new(model());
+            chat.with_history();
+            chat.global_params.stream_usage = true;
+
+            chat.send_completion(&client, Completion::user("hello"))
+                .await
+                .unwrap();
+            chat.send_completion_stream(&client, Completion::user("again"), |_| {})
+                .await
+                .unwrap();
+            chat.send_completion(&client, Completion::user("once more"))
+                .await
+                .unwrap();
+
+            let usage = chat.total_usage();
+            assert_eq!(usage.prompt_tokens, 9);
+            assert_eq!(usage.completion_tokens, 3);
+            assert_eq!(usage.total_tokens, 12);
+
+            chat.clear_history();
+            assert_eq!(chat.total_usage(), Usage::default());
+        }
+
+        #[tokio::test]
+        async fn error_messages_are_not_sent_back() {
+            let (mock, client) = mock_client();
+            mock.push_json(500, json!({ "error": "boom" }));
+            mock.push_json(200, reply("hi there"));
+            let mut chat = Chat::new(model());
+            chat.with_history();
+
+            assert!(chat
+                .send_completion(&client, Completion::user("hello"))
+                .await
+                .is_err());
+            chat.send_completion(&client, Completion::user("are you there?"))
+                .await
+                .unwrap();
+
+            assert_eq!(
+                sent_messages(&mock, 1),
+                [
+                    ("user".to_string(), "hello".to_string()),
+                    ("user".to_string(), "are you there?".to_string()),
+                ]
+            );
+            // the placeholder is still kept for display
+            assert_eq!(chat.message_count(), 4);
+        }
+
+        #[tokio::test]
+        async fn every_request_has_exactly_one_system_message() {
+            let (mock, client) = mock_client();
+            mock.push_json(200, reply("hi"));
+            mock.push_json(200, reply("sure"));
+            let mut chat = Chat::new(model());
+            chat.with_history()
+                .with_max_history_messages(1)
+                .with_system_prompt("be verbose")
+                .with_system_prompt("be brief");
+
+            chat.send_completion(&client, Completion::user("hello"))
+                .await
+                .unwrap();
+            chat.send_completion(&client, Completion::user("tell me more"))
+                .await
+                .unwrap();
+
+            for index in 0..2 {
+                let messages = sent_messages(&mock, index);
+                let system: Vec<_> = messages
+                    .iter()
+                    .filter(|(role, _)| role == "system")
+                    .collect();
+                assert_eq!(system.len(), 1);
+                assert_eq!(messages[0], ("system".to_string(), "be brief".to_string()));
+            }
+            assert!(chat.last_message_by_role(CompletionRole::SYSTEM).is_none());
+        }
+
+        #[tokio::test]
+        async fn seeded_history_is_sent_in_chronological_order() {
+            let (mock, client) = mock_client();
+            mock.push_json(200, reply("4"));
+            let mut chat = Chat::with_messages(
+                model(),
+                vec![Completion::user("1 + 1?"), Completion::assistant("2")],
+            );
+            chat.append_messages(vec![Completion::user("2 + 1?"), Completion::assistant("3")]);
+
+            chat.send_completion(&client, Completion::user("3 + 1?"))
+                .await
+                .unwrap();
+
+            let contents: Vec<String> = sent_messages(&mock, 0)
+                .into_iter()
+                .map(|(_, content)| content)
+                .collect();
+            assert_eq!(contents, ["1 + 1?", "2", "2 + 1?", "3", "3 + 1?"]);
+        }
+
+        #[tokio::test]
+        async fn generate_title_cleans_the_reply() {
+            let (mock, client) = mock_client();
+            mock.push_json(200, reply("\"Greeting the\nassistant\"\n"));
+            let mut chat = Chat::with_messages(
+                model(),
+                vec![Completion::user("hello"), Completion::assistant("hi")],
+            );
+
+            chat.generate_title(&client).await.unwrap();
+
+            assert_eq!(chat.title.as_deref(), Some("Greeting the assistant"));
+            let sent = sent_messages(&mock, 0);
+            assert_eq!(sent[1].1, "User: hello\nAssistant: hi");
+        }
+
+        #[tokio::test]
+        async fn generate_title_ignores_an_empty_reply() {
+            let (mock, client) = mock_client();
+            mock.push_json(200, reply("  "));
+            let mut chat = Chat::with_messages(model(), vec![Completion::user("hello")]);
+            chat.with_title("Old".to_string());
+
+            chat.generate_title(&client).await.unwrap();
+
+            assert_eq!(chat.title.as_deref(), Some("Old"));
+        }
+
+        #[tokio::test]
+        async fn generate_title_without_history_sends_nothing() {
+            let (mock, client) = mock_client();
+            let mut chat = Chat::new(model());
+
+            chat.generate_title(&client).await.unwrap();
+            chat.with_history().generate_title(&client).await.unwrap();
+
+            assert!(mock.requests().is_empty());
+            assert!(chat.title.is_none());
+        }
+    }
 }
@@ -0,0 +1,288 @@
+/*!
+Transport returning canned responses, for testing code built on this crate without network access
+
+```
+use aimlapi::{chat::*, client::Client, mock::MockTransport, model::Model};
+use serde_json::json;
+
+# #[tokio::main(flavor = "current_thread")]
+# async fn main() -> aimlapi::Result<()> {
+let mock = MockTransport::new();
+mock.push_json(200, json!({ "choices": [{ "message": { "role": "assistant", "content": "hi" } }] }));
+let client = Client::new("key").with_transport(mock.clone());
+
+let model = Model::from("gpt-4o".to_string());
+let msg = Completion::user("hello");
+let reply = client.send_completion(&model, msg, &CompletionParams::default(), &mut None).await?;
+assert_eq!(reply.content.text(), "hi");
+
+let request = &mock.requests()[0];
+assert_eq!(request.url, "https://api.aimlapi.com/chat/completions");
+assert_eq!(request.json().unwrap()["messages"][0]["content"], "hello");
+# Ok(())
+# }
+```
+
+`ReplayClient` builds on it to replay the assistant replies of a recorded transcript
+*/
+
+use std::{
+    collections::VecDeque,
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use futures_util::{future::BoxFuture, stream};
+use reqwest::{header::HeaderMap, Body, Method, Request, Response};
+use serde_json::{json, Value};
+
+use crate::{
+    chat::{Completion, CompletionParams, CompletionResponse, CompletionRole},
+    client::Client,
+    model::Model,
+    provider::CompletionProvider,
+    retry::RetryPolicy,
+    transport::HttpTransport,
+    Error,
+};
+
+/**
+Answers requests with queued responses in order and records every request it received
+
+Clones share the same queue and recordings, so keep a clone around to inspect what was sent
+
+Fails with `Error::InvalidInput` once a request arrives with no response queued
+*/
+#[derive(Debug, Default, Clone)]
+pub struct MockTransport {
+    responses: Arc<Mutex<VecDeque<MockResponse>>>,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+#[derive(Debug, Clone)]
+struct MockResponse {
+    status: u16,
+    headers: HeaderMap,
+    /// Sent as separate body chunks, a single chunk is sent with a content length
+    chunks: Vec<Vec<u8>>,
+}
+
+/// Request received by a `MockTransport`
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: Method,
+    pub url: String,
+    pub headers: HeaderMap,
+    /// None for streamed bodies like multipart uploads
+    pub body: Option<Vec<u8>>,
+}
+
+impl RecordedRequest {
+    /// Parses the body as JSON, None if there is no body or it's not JSON
+    pub fn json(&self) -> Option<Value> {
+        serde_json::from_slice(self.body.as_ref()?).ok()
+    }
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a response with the provided status and raw body, e.g. SSE lines for streams
+    pub fn push_response(&self, status: u16, body: impl Into<Vec<u8>>) -> &Self {
+        self.push_response_with_headers(status, HeaderMap::new(), body)
+    }
+
+    /// Queues a response with the provided status and JSON body
+    pub fn push_json(&self, status: u16, body: Value) -> &Self {
+        self.push_response(status, body.to_string())
+    }
+
+    /// Queues a response with the provided status, headers and raw body
+    pub fn push_response_with_headers(
+        &self,
+        status: u16,
+        headers: HeaderMap,
+        body: impl Into<Vec<u8>>,
+    ) -> &Self {
+        lock(&self.responses).push_back(MockResponse {
+            status,
+            headers,
+            chunks: vec![body.into()],
+        });
+        self
+    }
+
+    /**
+    Queues a response whose body arrives in the provided chunks, in order
+
+    Meant for streams, e.g. to split an SSE line across reads the way a slow network would
+    */
+    pub fn push_chunks(&self, status: u16, chunks: Vec<Vec<u8>>) -> &Self {
+        lock(&self.responses).push_back(MockResponse {
+            status,
+            headers: HeaderMap::new(),
+            chunks,
+        });
+        self
+    }
+
+    /// Returns every request received so far, oldest first
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        lock(&self.requests).clone()
+    }
+
+    /// Returns how many queued responses have not been used yet
+    pub fn pending_responses(&self) -> usize {
+        lock(&self.responses).len()
+    }
+}
+
+impl HttpTransport for MockTransport {
+    fn execute(&self, request: Request) -> BoxFuture<'_, crate::Result<Response>> {
+        let recorded = RecordedRequest {
+            method: request.method().clone(),
+            url: request.url().to_string(),
+            headers: request.headers().clone(),
+            body: request
+                .body()
+                .and_then(|body| body.as_bytes())
+                .map(<[u8]>::to_vec),
+        };
+        lock(&self.requests).push(recorded);
+        let res = lock(&self.responses).pop_front();
+
+        Box::pin(async move {
+            let res = res.ok_or_else(|| {
+                Error::InvalidInput("mock transport has no response queued".to_string())
+            })?;
+            let mut builder = http::Response::builder().status(res.status);
+            if let Some(headers) = builder.headers_mut() {
+                headers.extend(res.headers);
+            }
+            let body = match <[_; 1]>::try_from(res.chunks) {
+                Ok([body]) => Body::from(body),
+                Err(chunks) => Body::wrap_stream(stream::iter(
+                    chunks.into_iter().map(Ok::<_, std::io::Error>),
+                )),
+            };
+            let res = builder
+                .body(body)
+                .map_err(|err| Error::InvalidInput(format!("invalid mock response: {err}")))?;
+            Ok(Response::from(res))
+        })
+    }
+}
+
+/**
+Client answering completions with the assistant replies of a recorded transcript, in order
+
+Requests are built and history is handled exactly like with a regular `Client`, only the network
+is replaced, so apps can be tested fully offline and deterministically
+
+Fails with `Error::InvalidInput` once every reply was used
+*/
+#[derive(Debug, Clone)]
+pub struct ReplayClient {
+    client: Client,
+    replies: Arc<Mutex<VecDeque<Completion>>>,
+}
+
+impl ReplayClient {
+    /// Replays the assistant messages among `transcript`, other roles are skipped
+    pub fn new(transcript: Vec<Completion>) -> Self {
+        let replies: VecDeque<Completion> = transcript
+            .into_iter()
+            .filter(|msg| msg.get_role() == CompletionRole::ASSISTANT)
+            .collect();
+        let replies = Arc::new(Mutex::new(replies));
+        let client = Client::new("replay")
+            .with_retry_policy(RetryPolicy::none())
+            .with_transport(ReplayTransport {
+                replies: replies.clone(),
+            });
+        Self { client, replies }
+    }
+
+    /**
+    Loads a transcript saved as a JSON array of messages in chronological order
+
+    Fails if the file does not exist or is not a valid transcript
+    */
+    pub fn from_path(path: impl AsRef<Path>) -> crate::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        Ok(Self::new(serde_json::from_str(&json)?))
+    }
+
+    /// Returns the underlying client for apps that take a `Client`
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    /// Returns how many replies are left
+    pub fn remaining(&self) -> usize {
+        lock(&self.replies).len()
+    }
+
+    /// Returns the next reply and adds it to history as first element if history is enabled
+    pub async fn send_completion(
+        &self,
+        model: &Model,
+        msg: Completion,
+        params: &CompletionParams,
+        history: &mut Option<VecDeque<Completion>>,
+    ) -> crate::Result<Completion> {
+        self.client
+            .send_completion(model, msg, params, history)
+            .await
+    }
+
+    /// Returns the next reply as response, without touching any history
+    pub async fn send_messages(
+        &self,
+        model: &Model,
+        messages: &[Completion],
+        params: &CompletionParams,
+    ) -> crate::Result<CompletionResponse> {
+        self.client.send_messages(model, messages, params).await
+    }
+}
+
+impl CompletionProvider for ReplayClient {
+    fn complete<'a>(
+        &'a self,
+        model: &'a Model,
+        messages: &'a [Completion],
+        params: &'a CompletionParams,
+    ) -> BoxFuture<'a, crate::Result<Completion>> {
+        self.client.complete(model, messages, params)
+    }
+}
+
+#[derive(Debug)]
+struct ReplayTransport {
+    replies: Arc<Mutex<VecDeque<Completion>>>,
+}
+
+impl HttpTransport for ReplayTransport {
+    fn execute(&self, _request: Request) -> BoxFuture<'_, crate::Result<Response>> {
+        let reply = lock(&self.replies).pop_front();
+        Box::pin(async move {
+            let reply = reply.ok_or_else(|| {
+                Error::InvalidInput("replay transcript has no replies left".to_string())
+            })?;
+            let body = json!({ "choices": [{ "message": reply, "finish_reason": "stop" }] });
+            let res = http::Response::builder()
+                .status(200)
+                .body(body.to_string())
+                .map_err(|err| Error::InvalidInput(format!("invalid replay response: {err}")))?;
+            Ok(Response::from(res))
+        })
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
@@ -1,35 +1,370 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
 
 use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
 
-use crate::BASE_API_URL;
+use crate::client::Client;
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Model {
     pub name: String,
+    /// Overrides the parameter style inferred from the name, see `Model::param_style`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    param_style: Option<ParamStyle>,
 }
 
 impl From<String> for Model {
     fn from(value: String) -> Self {
-        Self { name: value }
+        Self {
+            name: value,
+            param_style: None,
+        }
     }
 }
 
+/**
+Which parameters a model accepts in completion requests
+
+`Reasoning` models (`o1`, `o3`, `o4` and `gpt-5` families) take `max_completion_tokens` instead of
+`max_tokens` and reject sampling parameters, so `temperature`, `top_p`, `frequency_penalty` and
+`presence_penalty` are left out for them
+*/
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ParamStyle {
+    #[default]
+    Classic,
+    Reasoning,
+}
+
+impl Model {
+    /**
+    Returns the context window in tokens for well-known models
+
+    None for models missing from the built-in table, prefer `ModelInfo::context_window`
+    when the model list was fetched
+    */
+    pub fn context_length(&self) -> Option<u32> {
+        known_context_length(&self.name)
+    }
+
+    /// Returns the parameter style requests for this model are sent with, inferred from the name unless overridden
+    pub fn param_style(&self) -> ParamStyle {
+        self.param_style
+            .unwrap_or_else(|| known_param_style(&self.name))
+    }
+
+    /// Overrides the inferred parameter style, for models the built-in rules don't know yet
+    pub fn with_param_style(mut self, param_style: ParamStyle) -> Self {
+        self.param_style = Some(param_style);
+        self
+    }
+}
+
+/**
+Metadata of a model as returned by `/models`
+
+Every field except the id is optional since not every model reports them
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(from = "ModelInfoRepr")]
+pub struct ModelInfo {
+    pub id: String,
+    /// Kind of model like `chat-completion`, `image` or `tts`
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub name: Option<String>,
+    pub developer: Option<String>,
+    pub description: Option<String>,
+    pub context_length: Option<u32>,
+    /// Capabilities like `openai/chat-completion.vision`
+    pub features: Vec<String>,
+}
+
+impl ModelInfo {
+    pub fn model(&self) -> Model {
+        Model::from(self.id.clone())
+    }
+
+    /// Returns the context length reported by the API, falling back to `Model::context_length`
+    pub fn context_window(&self) -> Option<u32> {
+        self.context_length
+            .or_else(|| known_context_length(&self.id))
+    }
+
+    /// Returns if the model has a feature, matching either the full name or its last segment like `vision`
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features
+            .iter()
+            .any(|own| own == feature || own.rsplit(['.', '/']).next() == Some(feature))
+    }
+
+    /// Returns if the model can be used with chat completions
+    pub fn is_chat_model(&self) -> bool {
+        self.kind.as_deref() == Some("chat-completion")
+    }
+
+    /// Returns if the model generates images
+    pub fn is_image_model(&self) -> bool {
+        self.kind.as_deref() == Some("image")
+    }
+
+    /// Returns if the model generates or transcribes speech
+    pub fn is_audio_model(&self) -> bool {
+        matches!(self.kind.as_deref(), Some("tts" | "stt" | "audio"))
+    }
+
+    /// Returns if the model accepts images as input
+    pub fn supports_vision(&self) -> bool {
+        self.has_feature("vision")
+    }
+
+    /// Returns if the model supports function calling
+    pub fn supports_tools(&self) -> bool {
+        self.has_feature("function") || self.has_feature("tools")
+    }
+}
+
+/**
+Keeps only the models matching the predicate
+
+E.g. `filter_models(get_models().await?, ModelInfo::is_chat_model)`
+*/
+pub fn filter_models(
+    models: Vec<ModelInfo>,
+    predicate: impl Fn(&ModelInfo) -> bool,
+) -> Vec<ModelInfo> {
+    models
+        .into_iter()
+        .filter(|model| predicate(model))
+        .collect()
+}
+
 /**
 Retrieves all AIMLAPI models
 
 Will return an error if request fails
 */
-pub async fn get_models() -> anyhow::Result<Vec<Model>> {
-    let res = reqwest::get(BASE_API_URL.to_string() + "/models")
-        .await?
-        .text()
-        .await?;
-
-    let model_map: HashMap<String, String> = serde_json::from_str(&res)?;
-    let models = model_map
-        .keys()
-        .map(|key| Model::from(key.to_owned()))
-        .collect();
-    Ok(models)
+pub async fn get_models() -> crate::Result<Vec<ModelInfo>> {
+    // the models endpoint does not require an api key
+    Client::new(String::new()).get_models().await
+}
+
+/**
+Same as `get_models` but authorizes with the api key, for deployments requiring it on `/models`
+
+Rate limited requests are retried with the default retry policy, will return an error if request fails
+*/
+pub async fn get_models_with_key(api_key: &str) -> crate::Result<Vec<ModelInfo>> {
+    Client::new(api_key).get_models().await
+}
+
+/**
+Looks up a model by its exact, case sensitive id
+
+Returns None if the API does not know the model, will return an error if request fails
+*/
+pub async fn get_model(name: &str) -> crate::Result<Option<ModelInfo>> {
+    Client::new(String::new()).get_model(name).await
+}
+
+/**
+Retrieves the names of all AIMLAPI models
+
+Will return an error if request fails
+*/
+pub async fn model_names() -> crate::Result<Vec<Model>> {
+    Ok(get_models().await?.iter().map(ModelInfo::model).collect())
+}
+
+/**
+Cache of the model list that is refetched once it's older than the ttl
+
+Concurrent callers share a single fetch instead of each hitting `/models`
+*/
+#[derive(Debug)]
+pub struct ModelCache {
+    client: Client,
+    ttl: Duration,
+    cached: Mutex<Option<(Instant, Vec<ModelInfo>)>>,
+}
+
+impl ModelCache {
+    pub fn new(client: Client, ttl: Duration) -> Self {
+        Self {
+            client,
+            ttl,
+            cached: Mutex::new(None),
+        }
+    }
+
+    /**
+    Returns the cached models if they're fresh, otherwise refetches them
+
+    Will return an error if request fails
+    */
+    pub async fn get_models_cached(&self) -> crate::Result<Vec<ModelInfo>> {
+        let mut cached = self.cached.lock().await;
+        if let Some((fetched_at, models)) = cached.as_ref() {
+            if fetched_at.elapsed() < self.ttl {
+                return Ok(models.clone());
+            }
+        }
+
+        let models = self.client.get_models().await?;
+        *cached = Some((Instant::now(), models.clone()));
+        Ok(models)
+    }
+
+    /**
+    Looks up a model by its exact, case sensitive id in the cached models
+
+    Returns None if the API does not know the model, will return an error if request fails
+    */
+    pub async fn get_model(&self, name: &str) -> crate::Result<Option<ModelInfo>> {
+        Ok(find_model(self.get_models_cached().await?, name))
+    }
+
+    /**
+    Refetches the models regardless of the cache's age
+
+    Will return an error if request fails, the cache is left untouched in that case
+    */
+    pub async fn force_refresh(&self) -> crate::Result<Vec<ModelInfo>> {
+        let mut cached = self.cached.lock().await;
+        let models = self.client.get_models().await?;
+        *cached = Some((Instant::now(), models.clone()));
+        Ok(models)
+    }
+}
+
+/// Known context windows by model name fragment, more specific fragments come first
+const KNOWN_CONTEXT_LENGTHS: &[(&str, u32)] = &[
+    ("gpt-4o", 128_000),
+    ("gpt-4.1", 1_047_576),
+    ("gpt-4-turbo", 128_000),
+    ("gpt-4-32k", 32_768),
+    ("gpt-4", 8_192),
+    ("gpt-3.5-turbo", 16_385),
+    ("o1-mini", 128_000),
+    ("o1", 200_000),
+    ("o3", 200_000),
+    ("o4-mini", 200_000),
+    ("claude-3", 200_000),
+    ("claude-sonnet-4", 200_000),
+    ("claude-opus-4", 200_000),
+    ("gemini-1.5-pro", 2_097_152),
+    ("gemini-1.5-flash", 1_048_576),
+    ("gemini-2.0-flash", 1_048_576),
+    ("llama-3.1", 131_072),
+    ("llama-3.2", 131_072),
+    ("llama-3.3", 131_072),
+    ("mistral-large", 128_000),
+];
+
+fn known_context_length(name: &str) -> Option<u32> {
+    // ids may carry a provider prefix like `meta-llama/` or `openai/`
+    let name = name.to_lowercase();
+    let name = name.rsplit('/').next().unwrap_or(&name);
+    KNOWN_CONTEXT_LENGTHS
+        .iter()
+        .find(|(fragment, _)| match *fragment {
+            // `o1` etc. would match too much anywhere else in a name
+            "o1" | "o1-mini" | "o3" | "o4-mini" => name.starts_with(fragment),
+            fragment => name.contains(fragment),
+        })
+        .map(|(_, length)| *length)
+}
+
+fn known_param_style(name: &str) -> ParamStyle {
+    let name = name.to_lowercase();
+    let name = name.rsplit('/').next().unwrap_or(&name);
+    let reasoning = ["o1", "o3", "o4", "gpt-5"]
+        .iter()
+        .any(|prefix| name.starts_with(prefix));
+    if reasoning {
+        ParamStyle::Reasoning
+    } else {
+        ParamStyle::Classic
+    }
+}
+
+pub(crate) fn find_model(models: Vec<ModelInfo>, name: &str) -> Option<ModelInfo> {
+    models.into_iter().find(|model| model.id == name)
+}
+
+/// Shapes the `/models` endpoint has been seen responding with
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum ModelsResponse {
+    List(Vec<ModelInfo>),
+    Data { data: Vec<ModelInfo> },
+    // older deployments respond with a map of model name to type
+    Map(HashMap<String, String>),
+}
+
+impl From<ModelsResponse> for Vec<ModelInfo> {
+    fn from(value: ModelsResponse) -> Self {
+        match value {
+            ModelsResponse::List(models) | ModelsResponse::Data { data: models } => models,
+            ModelsResponse::Map(map) => map
+                .into_iter()
+                .map(|(id, kind)| ModelInfo {
+                    id,
+                    kind: Some(kind),
+                    name: None,
+                    developer: None,
+                    description: None,
+                    context_length: None,
+                    features: Vec::new(),
+                })
+                .collect(),
+        }
+    }
+}
+
+// the API nests most metadata under `info`, flat fields are accepted too so saved models load back
+#[derive(Deserialize)]
+struct ModelInfoRepr {
+    id: String,
+    #[serde(default, rename = "type")]
+    kind: Option<String>,
+    #[serde(default)]
+    info: ModelInfoDetails,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    developer: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default, alias = "contextLength")]
+    context_length: Option<u32>,
+    #[serde(default)]
+    features: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct ModelInfoDetails {
+    name: Option<String>,
+    developer: Option<String>,
+    description: Option<String>,
+    context_length: Option<u32>,
+}
+
+impl From<ModelInfoRepr> for ModelInfo {
+    fn from(value: ModelInfoRepr) -> Self {
+        Self {
+            id: value.id,
+            kind: value.kind,
+            name: value.name.or(value.info.name),
+            developer: value.developer.or(value.info.developer),
+            description: value.description.or(value.info.description),
+            context_length: value.context_length.or(value.info.context_length),
+            features: value.features,
+        }
+    }
 }
@@ -0,0 +1,66 @@
+use std::collections::HashMap;
+
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{client::Client, Error};
+
+/**
+Checks the input against the moderation endpoint
+
+Will return an error if request fails
+*/
+pub async fn moderate(api_key: &str, input: &str) -> crate::Result<ModerationResult> {
+    Client::new(api_key).moderate(input).await
+}
+
+impl Client {
+    /**
+    Checks the input against the moderation endpoint
+
+    Will return an error if request fails or the response contains no result
+    */
+    pub async fn moderate(&self, input: &str) -> crate::Result<ModerationResult> {
+        let req = self
+            .request(Method::POST, "/moderations")
+            .json(&json!({ "input": input }));
+        let res = self.execute(req).await?;
+        let res = self.read_text("/moderations", res).await?;
+        let res: ModerationResponse = serde_json::from_str(&res)?;
+        res.results
+            .into_iter()
+            .next()
+            .ok_or_else(|| Error::InvalidResponse("moderation returned no results".to_string()))
+    }
+}
+
+/// Moderation verdict for a single input, categories are named like `hate` or `self-harm`
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ModerationResult {
+    pub flagged: bool,
+    #[serde(default)]
+    pub categories: HashMap<String, bool>,
+    /// Confidence per category between 0 and 1
+    #[serde(default)]
+    pub category_scores: HashMap<String, f64>,
+}
+
+impl ModerationResult {
+    /// Returns the names of every flagged category, sorted
+    pub fn flagged_categories(&self) -> Vec<String> {
+        let mut categories: Vec<String> = self
+            .categories
+            .iter()
+            .filter(|(_, flagged)| **flagged)
+            .map(|(category, _)| category.clone())
+            .collect();
+        categories.sort();
+        categories
+    }
+}
+
+#[derive(Deserialize)]
+struct ModerationResponse {
+    results: Vec<ModerationResult>,
+}
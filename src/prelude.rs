@@ -0,0 +1,22 @@
+/*!
+Commonly used types, `use aimlapi::prelude::*;` covers typical usage
+
+`aimlapi::Result` is left out on purpose since it would shadow `std::result::Result`
+*/
+
+pub use crate::{
+    chat::{
+        Completion, CompletionParams, CompletionResponse, CompletionRole, Content, FinishReason,
+        HistoryPolicy, ResponseFormat, Usage,
+    },
+    client::Client,
+    error::Error,
+    model::{Model, ModelInfo},
+    provider::CompletionProvider,
+    retry::RetryPolicy,
+    stream::{CompletionStream, StreamEvent},
+    tools::{Tool, ToolCall, ToolChoice},
+};
+
+#[cfg(feature = "managers")]
+pub use crate::managers::{Chat, ChatBuilder, ChatManager};
@@ -0,0 +1,40 @@
+use futures_util::future::BoxFuture;
+
+use crate::{
+    chat::{Completion, CompletionParams},
+    client::Client,
+    model::Model,
+    Error,
+};
+
+/**
+Common interface for backends answering completions, so apps can be generic over providers
+
+Object safe, store it as `Box<dyn CompletionProvider>` or `Arc<dyn CompletionProvider>` to pick a backend
+at runtime. Implemented for `Client` and, with the `test-util` feature, for `ReplayClient`
+
+Messages are expected in chronological order and no history is kept
+*/
+pub trait CompletionProvider: Send + Sync {
+    fn complete<'a>(
+        &'a self,
+        model: &'a Model,
+        messages: &'a [Completion],
+        params: &'a CompletionParams,
+    ) -> BoxFuture<'a, crate::Result<Completion>>;
+}
+
+impl CompletionProvider for Client {
+    fn complete<'a>(
+        &'a self,
+        model: &'a Model,
+        messages: &'a [Completion],
+        params: &'a CompletionParams,
+    ) -> BoxFuture<'a, crate::Result<Completion>> {
+        Box::pin(async move {
+            let res = self.send_messages(model, messages, params).await?;
+            let choice = res.choices.into_iter().next().ok_or(Error::EmptyChoices)?;
+            Ok(choice.message)
+        })
+    }
+}
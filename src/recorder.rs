@@ -0,0 +1,101 @@
+use std::{
+    fmt,
+    fs::OpenOptions,
+    io::Write,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Completion request and its outcome as recorded by a `RequestRecorder`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecordedExchange {
+    /// Seconds since the unix epoch the response arrived at
+    pub timestamp: u64,
+    pub path: String,
+    pub model: Option<String>,
+    /// Body that was sent
+    pub request: Value,
+    /**
+    Raw response body, None for failed requests
+
+    Streamed responses are recorded once the stream ended as the assembled reply in the shape of a
+    response that wasn't streamed, with whatever arrived before a failure
+    */
+    pub response: Option<String>,
+    /// Why the request failed, None if it succeeded
+    pub error: Option<String>,
+}
+
+/**
+Audit trail of every completion request sent by a `Client` and its response, see `Client::with_recorder`
+
+Api keys are only sent in headers which are never recorded, any occurrence of a key in a body is
+replaced with `[redacted]` as well. Streamed completions are recorded once the stream ended,
+failed or was dropped
+
+Writes to the file are not rotated or size limited
+*/
+#[derive(Clone)]
+pub struct RequestRecorder {
+    sink: Arc<dyn Fn(&RecordedExchange) + Send + Sync>,
+}
+
+impl fmt::Debug for RequestRecorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RequestRecorder").finish_non_exhaustive()
+    }
+}
+
+impl RequestRecorder {
+    /// Passes every exchange to the callback, e.g. to forward it to a logging system
+    pub fn from_fn(sink: impl Fn(&RecordedExchange) + Send + Sync + 'static) -> Self {
+        Self {
+            sink: Arc::new(sink),
+        }
+    }
+
+    /**
+    Appends every exchange as a JSON line to the file, creating it if needed
+
+    Fails if the file can't be opened, later write errors are ignored so they never fail a request
+    */
+    pub fn to_path(path: impl AsRef<Path>) -> crate::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let file = Mutex::new(file);
+        Ok(Self::from_fn(move |exchange| {
+            let Ok(mut line) = serde_json::to_string(exchange) else {
+                return;
+            };
+            line.push('\n');
+            let mut file = file.lock().unwrap_or_else(|err| err.into_inner());
+            let _ = file.write_all(line.as_bytes());
+        }))
+    }
+
+    /// Stamps the exchange with the current time, redacts the keys and passes it on
+    pub(crate) fn record(&self, mut exchange: RecordedExchange, keys: &[String]) {
+        exchange.timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        for key in keys.iter().filter(|key| !key.is_empty()) {
+            let request = exchange.request.to_string();
+            if request.contains(key.as_str()) {
+                exchange.request = serde_json::from_str(&request.replace(key.as_str(), REDACTED))
+                    .unwrap_or(Value::Null);
+            }
+            for text in [&mut exchange.response, &mut exchange.error]
+                .into_iter()
+                .flatten()
+            {
+                *text = text.replace(key.as_str(), REDACTED);
+            }
+        }
+        (self.sink)(&exchange);
+    }
+}
+
+const REDACTED: &str = "[redacted]";
@@ -0,0 +1,517 @@
+use std::{
+    collections::VecDeque,
+    future::Future,
+    io::Write,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use reqwest::Response;
+use serde_json::Value;
+use tokio::time::{Instant, Sleep};
+use tokio_util::sync::{CancellationToken, WaitForCancellationFutureOwned};
+
+use crate::{
+    chat::{Choice, Completion, CompletionResponse, CompletionRole, FinishReason, Usage},
+    client::ResponseMeta,
+    tools::{ToolCall, ToolCallAccumulator},
+    Error,
+};
+
+/// Event sent over the channel returned by `Client::send_messages_channel`
+#[derive(Debug)]
+pub enum StreamEvent {
+    /// Content received since the last event
+    Delta(String),
+    /// The assembled reply, always the last event of a successful stream
+    Done(Completion),
+    /// The request or the stream failed, always the last event
+    Error(Error),
+}
+
+type ByteStream = Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>>;
+
+/// Called once with the assembled reply as JSON, if anything arrived, and the error the stream ended with
+type EndHook = Box<dyn FnOnce(Option<String>, Option<&Error>) + Send>;
+
+/**
+Stream of content deltas from a streamed completion
+
+Yields every `choices[0].delta.content` as it arrives and ends once the API sends `data: [DONE]`
+
+Consume it with `futures_util::StreamExt`, e.g. `while let Some(delta) = stream.next().await`,
+or call `finish` to get the assembled reply. The finish reason and usage usually arrive with the
+last chunks, so they are only reliable once every content delta was yielded
+
+Generation can be stopped with `cancel_on` or by dropping the stream, either way the connection
+is closed instead of being returned to the client's pool since the body wasn't fully read
+*/
+pub struct CompletionStream {
+    bytes: ByteStream,
+    buffer: Vec<u8>,
+    /// `data:` lines of the event being received, dispatched once a blank line ends it
+    event: String,
+    pending: VecDeque<String>,
+    text: String,
+    done: bool,
+    idle_timeout: Duration,
+    idle: Pin<Box<Sleep>>,
+    cancelled: Option<Pin<Box<WaitForCancellationFutureOwned>>>,
+    meta: ResponseMeta,
+    tool_calls: ToolCallAccumulator,
+    finish_reason: Option<FinishReason>,
+    usage: Option<Usage>,
+    max_size: Option<usize>,
+    id: Option<String>,
+    created: Option<u64>,
+    system_fingerprint: Option<String>,
+    on_end: Option<EndHook>,
+}
+
+impl CompletionStream {
+    /**
+    `idle_timeout` is the longest gap allowed between two chunks before failing with `Error::Timeout`,
+    `max_size` caps the bytes of accumulated content and of a single buffered line or event
+    */
+    pub(crate) fn new(res: Response, idle_timeout: Duration, max_size: Option<usize>) -> Self {
+        Self {
+            meta: ResponseMeta::from_headers(res.headers()),
+            tool_calls: ToolCallAccumulator::new(),
+            finish_reason: None,
+            usage: None,
+            max_size,
+            id: None,
+            created: None,
+            system_fingerprint: None,
+            bytes: Box::pin(res.bytes_stream()),
+            buffer: Vec::new(),
+            event: String::new(),
+            pending: VecDeque::new(),
+            text: String::new(),
+            done: false,
+            idle_timeout,
+            idle: Box::pin(tokio::time::sleep(idle_timeout)),
+            cancelled: None,
+            on_end: None,
+        }
+    }
+
+    /**
+    Calls `hook` once the stream ended, failed or was dropped before its end
+
+    A dropped stream counts as failed with `Error::Cancelled`
+    */
+    pub(crate) fn on_end(
+        mut self,
+        hook: impl FnOnce(Option<String>, Option<&Error>) + Send + 'static,
+    ) -> Self {
+        self.on_end = Some(Box::new(hook));
+        self
+    }
+
+    /**
+    Stops the stream once the token is cancelled
+
+    The stream then yields `Error::Cancelled` and ends, `text` still returns everything received before
+    */
+    pub fn cancel_on(mut self, token: CancellationToken) -> Self {
+        self.cancelled = Some(Box::pin(token.cancelled_owned()));
+        self
+    }
+
+    /// Returns the request id and rate limit headers of the response
+    pub fn meta(&self) -> &ResponseMeta {
+        &self.meta
+    }
+
+    /// Returns all content received so far
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /**
+    Returns the tool calls completed since the last call, each call is only returned once
+
+    A call counts as complete once its streamed arguments form valid JSON,
+    tool call fragments don't yield any items so poll this after every item or once the stream ended
+    */
+    pub fn take_tool_calls(&mut self) -> Vec<ToolCall> {
+        self.tool_calls.take_ready()
+    }
+
+    /// Returns the finish reason once the API sent it, usually with the last content delta
+    pub fn finish_reason(&self) -> Option<&FinishReason> {
+        self.finish_reason.as_ref()
+    }
+
+    /// Returns the id the API assigned to the completion once the first chunk arrived
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /**
+    Returns the token usage once the API sent it, usually in a chunk after the last content delta
+
+    Only sent when the request enabled `CompletionParams::stream_usage`
+    */
+    pub fn usage(&self) -> Option<Usage> {
+        self.usage
+    }
+
+    /**
+    Consumes the rest of the stream and returns the assembled reply as a single choice response
+
+    The message holds all content and any tool calls, usage is None if the API did not report it
+
+    Fails with `Error::EmptyContent` if neither content nor tool calls arrived,
+    or if the rest of the stream failed, errors already yielded before are not repeated
+    */
+    pub async fn finish(mut self) -> crate::Result<CompletionResponse> {
+        while let Some(delta) = self.next().await {
+            delta?;
+        }
+        self.assemble().ok_or(Error::EmptyContent)
+    }
+
+    /**
+    Same as `finish` but also writes every content delta to `writer` as it arrives, e.g. to tee output to a log
+
+    Fails if the rest of the stream failed or writing failed
+    */
+    pub async fn write_to(mut self, writer: &mut impl Write) -> crate::Result<CompletionResponse> {
+        while let Some(delta) = self.next().await {
+            writer.write_all(delta?.as_bytes())?;
+            writer.flush()?;
+        }
+        self.finish().await
+    }
+
+    /// Returns every tool call received so far, including ones still streaming their arguments
+    pub fn tool_calls(&self) -> Vec<ToolCall> {
+        self.tool_calls.tool_calls()
+    }
+
+    /// Builds a single choice response from everything received so far, None if nothing arrived
+    fn assemble(&self) -> Option<CompletionResponse> {
+        let mut message = Completion::new(CompletionRole::ASSISTANT, &self.text);
+        let tool_calls = self.tool_calls.tool_calls();
+        if !tool_calls.is_empty() {
+            message.tool_calls = Some(tool_calls);
+        }
+        if message.is_empty() {
+            return None;
+        }
+        Some(CompletionResponse {
+            id: self.id.clone(),
+            created: self.created,
+            system_fingerprint: self.system_fingerprint.clone(),
+            choices: vec![Choice {
+                message,
+                finish_reason: self.finish_reason.clone(),
+                logprobs: None,
+            }],
+            usage: self.usage,
+            meta: self.meta.clone(),
+        })
+    }
+
+    /// Runs the end hook if it didn't run yet
+    fn end(&mut self, error: Option<&Error>) {
+        if let Some(hook) = self.on_end.take() {
+            let response = self
+                .assemble()
+                .and_then(|response| serde_json::to_string(&response).ok());
+            hook(response, error);
+        }
+    }
+
+    /// Polls the next delta, ends with None once the stream is done
+    fn poll_delta(&mut self, cx: &mut Context<'_>) -> Poll<Option<crate::Result<String>>> {
+        if let Some(cancelled) = self.cancelled.as_mut() {
+            if !self.done && cancelled.as_mut().poll(cx).is_ready() {
+                self.done = true;
+                self.pending.clear();
+                return Poll::Ready(Some(Err(Error::Cancelled)));
+            }
+        }
+
+        loop {
+            if let Some(delta) = self.pending.pop_front() {
+                return Poll::Ready(Some(Ok(delta)));
+            }
+            if self.done {
+                return Poll::Ready(None);
+            }
+
+            let chunk = match self.bytes.as_mut().poll_next(cx) {
+                Poll::Ready(chunk) => chunk,
+                Poll::Pending => {
+                    ready!(self.idle.as_mut().poll(cx));
+                    self.done = true;
+                    return Poll::Ready(Some(Err(Error::Timeout)));
+                }
+            };
+
+            match chunk {
+                Some(Ok(chunk)) => {
+                    let deadline = Instant::now() + self.idle_timeout;
+                    self.idle.as_mut().reset(deadline);
+                    self.buffer.extend_from_slice(&chunk);
+                    if let Err(err) = self.process_lines().and_then(|_| self.check_size()) {
+                        self.done = true;
+                        return Poll::Ready(Some(Err(err)));
+                    }
+                }
+                Some(Err(err)) => {
+                    self.done = true;
+                    return Poll::Ready(Some(Err(err.into())));
+                }
+                None => {
+                    // the connection closed, flush a last line and event without their ends
+                    self.done = true;
+                    let line = std::mem::take(&mut self.buffer);
+                    let res = self.process_line(&line).and_then(|_| self.dispatch_event());
+                    if let Err(err) = res {
+                        return Poll::Ready(Some(Err(err)));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Parses every complete line in the buffer, leaving a trailing partial line in place
+    fn process_lines(&mut self) -> crate::Result<()> {
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+            self.process_line(&line)?;
+            if self.done {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails once the content, a line without its end or an event without its end exceeds the max size
+    fn check_size(&self) -> crate::Result<()> {
+        match self.max_size {
+            Some(limit)
+                if self.text.len() > limit
+                    || self.buffer.len() > limit
+                    || self.event.len() > limit =>
+            {
+                Err(Error::ResponseTooLarge { limit })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /**
+    Handles a single SSE line, lines may end with `\n` or `\r\n`
+
+    A blank line ends an event and dispatches its `data:` lines joined by newlines,
+    comments like `: keep-alive` and other fields like `event:` or `id:` are ignored
+    */
+    fn process_line(&mut self, line: &[u8]) -> crate::Result<()> {
+        let line = String::from_utf8_lossy(line);
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.is_empty() {
+            return self.dispatch_event();
+        }
+        let Some(data) = line.strip_prefix("data:") else {
+            return Ok(());
+        };
+        let data = data.strip_prefix(' ').unwrap_or(data);
+
+        // some servers skip the blank line between events, an event that is already complete
+        // on its own can't be continued by another data line
+        if !self.event.is_empty() && is_complete_event(&self.event) {
+            self.dispatch_event()?;
+        }
+        if !self.event.is_empty() {
+            self.event.push('\n');
+        }
+        self.event.push_str(data);
+        Ok(())
+    }
+
+    fn dispatch_event(&mut self) -> crate::Result<()> {
+        let event = std::mem::take(&mut self.event);
+        let data = event.trim();
+        if data.is_empty() {
+            return Ok(());
+        }
+        if data == "[DONE]" {
+            self.done = true;
+            return Ok(());
+        }
+
+        let json: Value = serde_json::from_str(data)?;
+        if json["error"].is_object() {
+            let message = json["error"]["message"].as_str().unwrap_or("unknown error");
+            return Err(Error::Stream(message.to_string()));
+        }
+
+        // every chunk repeats the id and creation time of the completion
+        if self.id.is_none() {
+            self.id = json["id"].as_str().map(str::to_string);
+            self.created = json["created"].as_u64();
+        }
+        if let Some(fingerprint) = json["system_fingerprint"].as_str() {
+            self.system_fingerprint = Some(fingerprint.to_string());
+        }
+        if json["usage"].is_object() {
+            self.usage = Some(serde_json::from_value(json["usage"].clone())?);
+        }
+        if let Some(reason) = json["choices"][0]["finish_reason"].as_str() {
+            self.finish_reason = Some(FinishReason::from(reason.to_string()));
+        }
+
+        let delta = &json["choices"][0]["delta"];
+        if let Some(fragments) = delta["tool_calls"].as_array() {
+            for fragment in fragments {
+                self.tool_calls.push(fragment);
+            }
+        }
+
+        // chunks without content (role announcements, usage, tool calls) are skipped
+        if let Some(content) = delta["content"].as_str() {
+            if !content.is_empty() {
+                self.text.push_str(content);
+                self.pending.push_back(content.to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Stream for CompletionStream {
+    type Item = crate::Result<String>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let item = ready!(this.poll_delta(cx));
+        // every error ends the stream
+        match &item {
+            Some(Ok(_)) => {}
+            Some(Err(err)) => this.end(Some(err)),
+            None => this.end(None),
+        }
+        Poll::Ready(item)
+    }
+}
+
+impl Drop for CompletionStream {
+    fn drop(&mut self) {
+        self.end(Some(&Error::Cancelled));
+    }
+}
+
+fn is_complete_event(data: &str) -> bool {
+    let data = data.trim();
+    data == "[DONE]" || serde_json::from_str::<serde::de::IgnoredAny>(data).is_ok()
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{client::Client, mock::MockTransport, model::Model, retry::RetryPolicy};
+
+    /// SSE event carrying a single content delta
+    fn delta(content: &str) -> String {
+        let chunk = json!({ "choices": [{ "delta": { "content": content } }] });
+        format!("data: {chunk}\n\n")
+    }
+
+    /// Streams a completion whose body arrives in the provided chunks and returns the yielded deltas
+    async fn stream_chunks(chunks: Vec<Vec<u8>>) -> (Vec<String>, CompletionStream) {
+        let mock = MockTransport::new();
+        mock.push_chunks(200, chunks);
+        let client = Client::new("key")
+            .with_retry_policy(RetryPolicy::none())
+            .with_transport(mock);
+        let model = Model::from("gpt-4o".to_string());
+        let messages = [Completion::user("hi")];
+        let mut stream = client
+            .send_messages_stream(&model, &messages, &Default::default())
+            .await
+            .unwrap();
+        let mut deltas = Vec::new();
+        while let Some(delta) = stream.next().await {
+            deltas.push(delta.unwrap());
+        }
+        (deltas, stream)
+    }
+
+    /// Splits `body` at the provided byte offsets
+    fn split_at(body: &[u8], offsets: &[usize]) -> Vec<Vec<u8>> {
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        for &offset in offsets {
+            chunks.push(body[start..offset].to_vec());
+            start = offset;
+        }
+        chunks.push(body[start..].to_vec());
+        chunks
+    }
+
+    #[tokio::test]
+    async fn data_lines_split_across_chunks() {
+        let body = format!("{}{}data: [DONE]\n\n", delta("Hello"), delta(" world"));
+        let second = body.find(" world").unwrap();
+        // split inside the prefix, inside the json and right before the newline ending a line
+        let offsets = [3, 20, second, body.find("\n\ndata: [DONE]").unwrap()];
+        let (deltas, stream) = stream_chunks(split_at(body.as_bytes(), &offsets)).await;
+        assert_eq!(deltas, ["Hello", " world"]);
+        assert_eq!(stream.text(), "Hello world");
+    }
+
+    #[tokio::test]
+    async fn utf8_split_inside_a_character() {
+        let body = format!("{}data: [DONE]\n\n", delta("héllo 👋"));
+        let accent = body.find('é').unwrap() + 1;
+        let emoji = body.find('👋').unwrap() + 2;
+        let (deltas, _) = stream_chunks(split_at(body.as_bytes(), &[accent, emoji])).await;
+        assert_eq!(deltas, ["héllo 👋"]);
+    }
+
+    #[tokio::test]
+    async fn crlf_line_endings() {
+        let body = format!("{}{}data: [DONE]\n\n", delta("a"), delta("b")).replace('\n', "\r\n");
+        // split between the carriage return and the newline
+        let offset = body.find("\r\n").unwrap() + 1;
+        let (deltas, _) = stream_chunks(split_at(body.as_bytes(), &[offset])).await;
+        assert_eq!(deltas, ["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn comment_lines_are_ignored() {
+        let body = format!(
+            ": keep-alive\n\n{}:another comment\n{}data: [DONE]\n\n",
+            delta("a"),
+            delta("b")
+        );
+        let (deltas, _) = stream_chunks(split_at(body.as_bytes(), &[5])).await;
+        assert_eq!(deltas, ["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn missing_trailing_blank_line() {
+        let body = format!("{}{}", delta("a"), delta("b").trim_end());
+        let (deltas, stream) = stream_chunks(split_at(body.as_bytes(), &[10])).await;
+        assert_eq!(deltas, ["a", "b"]);
+        assert_eq!(stream.text(), "ab");
+    }
+
+    #[tokio::test]
+    async fn events_without_blank_lines_between_them() {
+        let body = format!("{}{}data: [DONE]", delta("a"), delta("b")).replace("\n\n", "\n");
+        let (deltas, _) = stream_chunks(vec![body.into_bytes()]).await;
+        assert_eq!(deltas, ["a", "b"]);
+    }
+}
@@ -0,0 +1,178 @@
+use crate::model::Model;
+
+/**
+Estimates how many tokens a text takes up for the provided model without running a tokenizer
+
+Common words count as a single token and longer ones as one more token per few extra characters,
+digits count in groups of three and punctuation and CJK characters one token each
+
+On everyday English prose this lands within a few percent of the real count, short texts, rare words,
+code identifiers and non English text can be off by 20% or more since the real tokenizer splits them
+into pieces this estimate can't know about, e.g. `tiktoken` takes up three tokens instead of one
+
+Models with larger vocabularies (e.g. `gpt-4o` and newer OpenAI models) split words less often
+*/
+pub fn count_tokens(text: &str, model: &Model) -> usize {
+    let whole_word = whole_word_chars(model);
+    let word_tokens = |len: usize| match len {
+        0 => 0,
+        // vocabularies hold most words whole, longer ones are split into pieces of a few characters
+        len if len <= whole_word => 1,
+        len => 1 + (len - whole_word).div_ceil(4),
+    };
+    let mut tokens: usize = 0;
+    let mut word: usize = 0;
+    let mut digits: usize = 0;
+    let mut newline = false;
+
+    for c in text.chars() {
+        if c.is_ascii_digit() {
+            tokens += word_tokens(word);
+            word = 0;
+            digits += 1;
+            continue;
+        }
+        tokens += digits.div_ceil(3);
+        digits = 0;
+
+        if c.is_alphabetic() && !is_cjk(c) {
+            // non ascii letters are usually encoded as several bytes and split more often
+            word += if c.is_ascii() { 1 } else { 2 };
+            continue;
+        }
+        tokens += word_tokens(word);
+        word = 0;
+
+        if c == '\n' {
+            // consecutive newlines are merged into a single token
+            tokens += usize::from(!newline);
+            newline = true;
+            continue;
+        }
+        newline = false;
+
+        // other whitespace is merged into the following word
+        if !c.is_whitespace() {
+            tokens += 1;
+        }
+    }
+
+    tokens + word_tokens(word) + digits.div_ceil(3)
+}
+
+/// Longest word counted as a single token
+fn whole_word_chars(model: &Model) -> usize {
+    let name = model.name.to_lowercase();
+    let name = name.rsplit('/').next().unwrap_or(&name);
+    let large_vocab = ["gpt-4o", "gpt-4.1", "gpt-5", "o1", "o3", "o4", "chatgpt-4o"]
+        .iter()
+        .any(|prefix| name.starts_with(prefix));
+    if large_vocab {
+        14
+    } else {
+        12
+    }
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30ff}' // hiragana and katakana
+        | '\u{3400}'..='\u{4dbf}'
+        | '\u{4e00}'..='\u{9fff}'
+        | '\u{ac00}'..='\u{d7af}' // hangul
+        | '\u{f900}'..='\u{faff}'
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /**
+    Realistic prompts with their token count under `cl100k_base`, the tokenizer of `gpt-4`
+
+    They avoid apostrophes and line breaks, so every word is a single token and the count is
+    the number of words plus the number of punctuation marks
+    */
+    const CL100K_PROMPTS: [(&str, usize); 3] = [
+        (
+            "You are a helpful assistant for a small company that sells garden tools. Answer \
+             questions about orders, shipping and returns in a friendly tone. If you do not know \
+             the answer, say so and offer to connect the customer with a member of our support \
+             team. Keep every answer short and clear, and never share information about other \
+             customers.",
+            66,
+        ),
+        (
+            "Can you explain the difference between a process and a thread in simple terms? I am \
+             learning about operating systems for the first time and the book I am reading uses a \
+             lot of words I do not understand yet. Please give me a short example of each, and \
+             tell me when I should use one or the other.",
+            63,
+        ),
+        (
+            "Write a function that takes a list of numbers and returns the largest value. The \
+             function should work for empty lists as well and return nothing in that case. Add a \
+             few tests that show how it behaves with negative numbers, with repeated values and \
+             with a single item.",
+            53,
+        ),
+    ];
+
+    /// Short texts with their token count under `cl100k_base`, including words the tokenizer splits
+    const CL100K_SHORT: [(&str, usize); 6] = [
+        ("hello world", 2),
+        ("Hello, world!", 4),
+        ("The quick brown fox jumps over the lazy dog.", 10),
+        ("tiktoken is great!", 6),
+        ("antidisestablishmentarianism", 6),
+        ("お誕生日おめでとう", 9),
+    ];
+
+    #[test]
+    fn prose_estimates_are_within_a_few_percent() {
+        for name in ["gpt-4", "gpt-4o"] {
+            let model = Model::from(name.to_string());
+            for (text, expected) in CL100K_PROMPTS {
+                let estimated = count_tokens(text, &model);
+                assert!(
+                    estimated.abs_diff(expected) * 100 <= expected * 3,
+                    "{name} estimated {estimated} tokens, expected {expected} for {text:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn short_estimates_are_rough() {
+        let model = Model::from("gpt-4".to_string());
+        let mut expected_total = 0;
+        let mut estimated_total = 0;
+        for (text, expected) in CL100K_SHORT {
+            expected_total += expected;
+            estimated_total += count_tokens(text, &model);
+        }
+        // rare words like `tiktoken` are split by the tokenizer, which the estimate can't know about
+        assert!(estimated_total.abs_diff(expected_total) * 100 <= expected_total * 20);
+    }
+
+    #[test]
+    fn long_words_take_up_several_tokens() {
+        let model = Model::from("gpt-4".to_string());
+        assert_eq!(count_tokens("information", &model), 1);
+        assert_eq!(count_tokens("antidisestablishmentarianism", &model), 5);
+    }
+
+    #[test]
+    fn digits_are_grouped_in_threes() {
+        let model = Model::from("gpt-4".to_string());
+        assert_eq!(count_tokens("1234567", &model), 3);
+    }
+
+    #[test]
+    fn empty_text_has_no_tokens() {
+        let model = Model::from("gpt-4o".to_string());
+        assert_eq!(count_tokens("", &model), 0);
+        assert_eq!(count_tokens("   ", &model), 0);
+    }
+}
@@ -0,0 +1,387 @@
+use std::collections::BTreeMap;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+/**
+Function the model may call
+
+`parameters` is a JSON schema describing the function's arguments
+*/
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(from = "ToolRepr", into = "ToolRepr")]
+pub struct Tool {
+    pub name: String,
+    pub description: Option<String>,
+    pub parameters: Value,
+}
+
+impl Tool {
+    pub fn new(name: &str, description: &str, parameters: Value) -> Self {
+        Self {
+            name: name.to_string(),
+            description: Some(description.to_string()),
+            parameters,
+        }
+    }
+}
+
+/// Controls whether and which tool the model calls
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(try_from = "ToolChoiceRepr", into = "ToolChoiceRepr")]
+pub enum ToolChoice {
+    /// The model never calls a tool
+    None,
+    /// The model decides whether to call a tool
+    Auto,
+    /// The model must call at least one tool
+    Required,
+    /// The model must call the function with the provided name
+    Function(String),
+}
+
+/// Tool call requested by the model, `arguments` is the raw JSON string the model generated
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(from = "ToolCallRepr", into = "ToolCallRepr")]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: String,
+}
+
+impl ToolCall {
+    /**
+    Deserializes the arguments into a user type
+
+    Fails if the model generated invalid JSON or it doesn't match the type
+    */
+    pub fn parse_arguments<T: DeserializeOwned>(&self) -> crate::Result<T> {
+        Ok(serde_json::from_str(&self.arguments)?)
+    }
+}
+
+/**
+Reassembles tool calls from streamed `delta.tool_calls` fragments
+
+The id and name usually arrive in the first fragment of a call, the arguments are split
+across many fragments as raw text, fragments are matched to their call by `index`
+*/
+#[derive(Debug, Default, Clone)]
+pub struct ToolCallAccumulator {
+    partial: BTreeMap<u64, PartialToolCall>,
+}
+
+#[derive(Debug, Default, Clone)]
+struct PartialToolCall {
+    id: String,
+    name: String,
+    arguments: String,
+    taken: bool,
+}
+
+impl PartialToolCall {
+    fn to_tool_call(&self) -> ToolCall {
+        ToolCall {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            arguments: self.arguments.clone(),
+        }
+    }
+}
+
+impl ToolCallAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a single element of a chunk's `delta.tool_calls` array
+    pub fn push(&mut self, fragment: &Value) {
+        let index = fragment["index"].as_u64().unwrap_or(0);
+        let call = self.partial.entry(index).or_default();
+        if let Some(id) = fragment["id"].as_str() {
+            call.id = id.to_string();
+        }
+        if let Some(name) = fragment["function"]["name"].as_str() {
+            call.name.push_str(name);
+        }
+        if let Some(arguments) = fragment["function"]["arguments"].as_str() {
+            call.arguments.push_str(arguments);
+        }
+    }
+
+    /**
+    Returns the calls whose arguments form valid JSON by now, each call is only returned once
+
+    Calls are returned in index order
+    */
+    pub fn take_ready(&mut self) -> Vec<ToolCall> {
+        self.partial
+            .values_mut()
+            .filter(|call| {
+                !call.taken
+                    && !call.name.is_empty()
+                    && serde_json::from_str::<Value>(&call.arguments).is_ok()
+            })
+            .map(|call| {
+                call.taken = true;
+                call.to_tool_call()
+            })
+            .collect()
+    }
+
+    /// Returns every call received so far in index order, including ones with incomplete arguments
+    pub fn tool_calls(&self) -> Vec<ToolCall> {
+        self.partial
+            .values()
+            .map(PartialToolCall::to_tool_call)
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.partial.is_empty()
+    }
+}
+
+// wire representations, the API nests everything under a `function` object
+
+#[derive(Serialize, Deserialize)]
+struct ToolRepr {
+    #[serde(rename = "type")]
+    kind: String,
+    function: FunctionRepr,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FunctionRepr {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    #[serde(default)]
+    parameters: Value,
+}
+
+impl From<ToolRepr> for Tool {
+    fn from(value: ToolRepr) -> Self {
+        Self {
+            name: value.function.name,
+            description: value.function.description,
+            parameters: value.function.parameters,
+        }
+    }
+}
+
+impl From<Tool> for ToolRepr {
+    fn from(value: Tool) -> Self {
+        Self {
+            kind: "function".to_string(),
+            function: FunctionRepr {
+                name: value.name,
+                description: value.description,
+                parameters: value.parameters,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum ToolChoiceRepr {
+    Mode(String),
+    Function {
+        #[serde(rename = "type")]
+        kind: String,
+        function: FunctionNameRepr,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+struct FunctionNameRepr {
+    name: String,
+}
+
+impl TryFrom<ToolChoiceRepr> for ToolChoice {
+    type Error = String;
+
+    fn try_from(value: ToolChoiceRepr) -> Result<Self, Self::Error> {
+        match value {
+            ToolChoiceRepr::Mode(mode) => match mode.as_str() {
+                "none" => Ok(Self::None),
+                "auto" => Ok(Self::Auto),
+                "required" => Ok(Self::Required),
+                _ => Err(format!("unknown tool choice {mode}")),
+            },
+            ToolChoiceRepr::Function { function, .. } => Ok(Self::Function(function.name)),
+        }
+    }
+}
+
+impl From<ToolChoice> for ToolChoiceRepr {
+    fn from(value: ToolChoice) -> Self {
+        match value {
+            ToolChoice::None => Self::Mode("none".to_string()),
+            ToolChoice::Auto => Self::Mode("auto".to_string()),
+            ToolChoice::Required => Self::Mode("required".to_string()),
+            ToolChoice::Function(name) => Self::Function {
+                kind: "function".to_string(),
+                function: FunctionNameRepr { name },
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ToolCallRepr {
+    id: String,
+    #[serde(rename = "type")]
+    kind: String,
+    function: FunctionCallRepr,
+}
+
+#[derive(Serialize, Deserialize)]
+struct FunctionCallRepr {
+    name: String,
+    arguments: String,
+}
+
+impl From<ToolCallRepr> for ToolCall {
+    fn from(value: ToolCallRepr) -> Self {
+        Self {
+            id: value.id,
+            name: value.function.name,
+            arguments: value.function.arguments,
+        }
+    }
+}
+
+impl From<ToolCall> for ToolCallRepr {
+    fn from(value: ToolCall) -> Self {
+        Self {
+            id: value.id,
+            kind: "function".to_string(),
+            function: FunctionCallRepr {
+                name: value.name,
+                arguments: value.arguments,
+            },
+        }
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use std::collections::VecDeque;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        chat::{Completion, CompletionParams, FinishReason},
+        client::Client,
+        mock::MockTransport,
+        model::Model,
+        retry::RetryPolicy,
+    };
+
+    #[derive(Deserialize)]
+    struct WeatherArgs {
+        city: String,
+    }
+
+    /// Stub tool answering every city with the same forecast
+    fn get_weather(args: &WeatherArgs) -> String {
+        format!("sunny in {}", args.city)
+    }
+
+    #[tokio::test]
+    async fn tool_call_round_trip() {
+        let mock = MockTransport::new();
+        mock.push_json(
+            200,
+            json!({
+                "choices": [{
+                    "message": {
+                        "role": "assistant",
+                        "content": null,
+                        "tool_calls": [{
+                            "id": "call_1",
+                            "type": "function",
+                            "function": { "name": "get_weather", "arguments": "{\"city\":\"Paris\"}" }
+                        }]
+                    },
+                    "finish_reason": "tool_calls"
+                }]
+            }),
+        );
+        mock.push_json(
+            200,
+            json!({
+                "choices": [{
+                    "message": { "role": "assistant", "content": "It is sunny in Paris." },
+                    "finish_reason": "stop"
+                }]
+            }),
+        );
+        let client = Client::new("key")
+            .with_retry_policy(RetryPolicy::none())
+            .with_transport(mock.clone());
+        let model = Model::from("gpt-4o".to_string());
+        let tool = Tool::new(
+            "get_weather",
+            "Returns the forecast for a city",
+            json!({
+                "type": "object",
+                "properties": { "city": { "type": "string" } },
+                "required": ["city"]
+            }),
+        );
+        let params = CompletionParams::builder()
+            .tools(vec![tool])
+            .tool_choice(ToolChoice::Auto)
+            .build()
+            .unwrap();
+        let mut history = Some(VecDeque::new());
+
+        let res = client
+            .send_completion_response(
+                &model,
+                Completion::user("weather in paris?"),
+                &params,
+                &mut history,
+            )
+            .await
+            .unwrap();
+        assert_eq!(res.choices[0].finish_reason, Some(FinishReason::ToolCalls));
+        let call = &res.choices[0].message.tool_calls.as_ref().unwrap()[0];
+        assert_eq!(call.id, "call_1");
+        assert_eq!(call.name, "get_weather");
+
+        let result = get_weather(&call.parse_arguments().unwrap());
+        let reply = client
+            .send_completion(
+                &model,
+                Completion::tool(&call.id, &result),
+                &params,
+                &mut history,
+            )
+            .await
+            .unwrap();
+        assert_eq!(reply.content.text(), "It is sunny in Paris.");
+
+        let first = mock.requests()[0].json().unwrap();
+        assert_eq!(first["tools"][0]["type"], "function");
+        assert_eq!(first["tools"][0]["function"]["name"], "get_weather");
+        assert_eq!(first["tool_choice"], "auto");
+
+        let second = mock.requests()[1].json().unwrap();
+        let messages = second["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[1]["role"], "assistant");
+        assert_eq!(messages[1]["tool_calls"][0]["id"], "call_1");
+        assert_eq!(
+            messages[1]["tool_calls"][0]["function"]["arguments"],
+            "{\"city\":\"Paris\"}"
+        );
+        assert_eq!(messages[2]["role"], "tool");
+        assert_eq!(messages[2]["tool_call_id"], "call_1");
+        assert_eq!(messages[2]["content"], "sunny in Paris");
+    }
+}
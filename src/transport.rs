@@ -0,0 +1,21 @@
+use futures_util::future::BoxFuture;
+use reqwest::{Request, Response};
+
+/**
+Sends the requests built by a `Client`
+
+Implemented for `reqwest::Client` which is used by default, swap it with `Client::with_transport`
+to route requests elsewhere or to return canned responses in tests (see `MockTransport` behind the
+`test-util` feature)
+
+Retries, timeouts and status handling stay in the client, a transport only sends a single request
+*/
+pub trait HttpTransport: Send + Sync {
+    fn execute(&self, request: Request) -> BoxFuture<'_, crate::Result<Response>>;
+}
+
+impl HttpTransport for reqwest::Client {
+    fn execute(&self, request: Request) -> BoxFuture<'_, crate::Result<Response>> {
+        Box::pin(async move { Ok(reqwest::Client::execute(self, request).await?) })
+    }
+}
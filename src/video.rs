@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    client::Client,
+    generation::{Generation, GenerationId},
+    model::Model,
+};
+
+const VIDEO_PATH: &str = "/v2/generate/video/generation";
+
+/**
+Starts generating a video from the prompt and returns the job's id, poll it with `fetch_generation`
+
+Will return an error if request fails
+*/
+pub async fn generate_video(
+    api_key: &str,
+    model: &Model,
+    prompt: &str,
+    params: &VideoParams,
+) -> crate::Result<GenerationId> {
+    Client::new(api_key)
+        .generate_video(model, prompt, params)
+        .await
+}
+
+/// Retrieves the current state of a video generation, the url is set once it completed
+pub async fn fetch_generation(api_key: &str, id: &GenerationId) -> crate::Result<Generation> {
+    Client::new(api_key).fetch_video(id).await
+}
+
+/**
+Polls a video generation every `poll_interval` until it completed or failed
+
+A failed generation is returned as well, check its status and error.
+Fails with `Error::Timeout` if the generation is still unfinished after `max_wait`
+*/
+pub async fn wait_for_completion(
+    api_key: &str,
+    id: &GenerationId,
+    poll_interval: Duration,
+    max_wait: Duration,
+) -> crate::Result<Generation> {
+    Client::new(api_key)
+        .wait_for_video(id, poll_interval, max_wait)
+        .await
+}
+
+impl Client {
+    /**
+    Starts generating a video from the prompt and returns the job's id, poll it with `fetch_video`
+
+    Will return an error if request fails
+    */
+    pub async fn generate_video(
+        &self,
+        model: &Model,
+        prompt: &str,
+        params: &VideoParams,
+    ) -> crate::Result<GenerationId> {
+        let mut json = json!(params);
+        json["model"] = json!(model.name);
+        json["prompt"] = json!(prompt);
+        self.start_generation(VIDEO_PATH, &json).await
+    }
+
+    /// Retrieves the current state of a video generation, the url is set once it completed
+    pub async fn fetch_video(&self, id: &GenerationId) -> crate::Result<Generation> {
+        self.fetch_generation_at(VIDEO_PATH, id).await
+    }
+
+    /**
+    Polls a video generation every `poll_interval` until it completed or failed
+
+    A failed generation is returned as well, check its status and error.
+    Fails with `Error::Timeout` if the generation is still unfinished after `max_wait`
+    */
+    pub async fn wait_for_video(
+        &self,
+        id: &GenerationId,
+        poll_interval: Duration,
+        max_wait: Duration,
+    ) -> crate::Result<Generation> {
+        self.wait_for_generation_at(VIDEO_PATH, id, poll_interval, max_wait)
+            .await
+    }
+}
+
+/// Optional parameters for video generation, unset ones are left to the model's defaults
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct VideoParams {
+    /// Length in seconds, supported lengths depend on the model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u32>,
+    /// Ratio like `16:9` or `9:16`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aspect_ratio: Option<String>,
+    /// Url of an image to animate, for image-to-video models
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod tests {
+    use super::*;
+    use crate::{generation::GenerationStatus, mock::MockTransport, retry::RetryPolicy, Error};
+
+    fn mock_client() -> (MockTransport, Client) {
+        let mock = MockTransport::new();
+        let client = Client::new("key")
+            .with_retry_policy(RetryPolicy::none())
+            .with_transport(mock.clone());
+        (mock, client)
+    }
+
+    fn id() -> GenerationId {
+        GenerationId("gen-1".to_string())
+    }
+
+    #[tokio::test]
+    async fn waits_until_the_generation_completed() {
+        let (mock, client) = mock_client();
+        mock.push_json(200, json!({ "id": "gen-1", "status": "generating" }));
+        mock.push_json(
+            200,
+            json!({ "id": "gen-1", "status": "completed", "video": { "url": "https://cdn/v.mp4" } }),
+        );
+
+        let generation = client
+            .wait_for_video(&id(), Duration::from_millis(1), Duration::from_secs(5))
+            .await
+            .unwrap();
+
+        assert_eq!(generation.status, GenerationStatus::Completed);
+        assert_eq!(generation.url.as_deref(), Some("https://cdn/v.mp4"));
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn cancelled_generations_are_finished() {
+        let (mock, client) = mock_client();
+        mock.push_json(200, json!({ "id": "gen-1", "status": "cancelled" }));
+
+        let generation = client
+            .wait_for_video(&id(), Duration::from_millis(1), Duration::from_secs(5))
+            .await
+            .unwrap();
+
+        assert_eq!(generation.status, GenerationStatus::Failed);
+    }
+
+    #[tokio::test]
+    async fn unfinished_generations_time_out() {
+        let (mock, client) = mock_client();
+        mock.push_json(200, json!({ "id": "gen-1", "status": "queued" }));
+
+        let res = client
+            .wait_for_video(&id(), Duration::from_secs(1), Duration::ZERO)
+            .await;
+
+        assert!(matches!(res, Err(Error::Timeout)));
+        assert_eq!(mock.requests().len(), 1);
+    }
+}